@@ -0,0 +1,252 @@
+use crate::{
+    error::BftError,
+    types::{Address, CompactVotes, Node, Target, Vote, VoteType},
+};
+
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+#[cfg(feature = "bls")]
+mod bls;
+
+#[cfg(feature = "bls")]
+pub use self::bls::BlsAggregator;
+
+/// A signature scheme whose signatures of one message can be combined into one
+/// signature. The public key of an authority is its address.
+pub trait Aggregator: Send + Sync + fmt::Debug {
+    /// Sign the message with the local key.
+    fn sign(&self, msg: &[u8]) -> Vec<u8>;
+    /// Verify the signature of the message by the signer.
+    fn verify(&self, msg: &[u8], signer: &Address, signature: &[u8]) -> bool;
+    /// Combine the signatures of one message into one, none if any of them is
+    /// malformed.
+    fn aggregate(&self, signatures: &[&[u8]]) -> Option<Vec<u8>>;
+    /// Verify the aggregate signature of the message by all the signers.
+    fn verify_aggregate(&self, msg: &[u8], signers: &[&Address], signature: &[u8]) -> bool;
+}
+
+/// The message to sign of a vote. The voter is not in the message, so that the
+/// votes on the same proposal can be aggregated.
+pub fn vote_message(vote: &Vote) -> Vec<u8> {
+    message(&vote.vote_type, vote.height, vote.round, &vote.proposal)
+}
+
+fn message(vote_type: &VoteType, height: u64, round: u64, proposal: &Target) -> Vec<u8> {
+    let mut msg = Vec::new();
+    msg.push(match vote_type {
+        VoteType::Prevote => 0,
+        VoteType::Precommit => 1,
+        VoteType::Generic => 2,
+    });
+    msg.extend_from_slice(&height.to_be_bytes());
+    msg.extend_from_slice(&round.to_be_bytes());
+    msg.extend_from_slice(&proposal.clone().into_vec());
+    msg
+}
+
+/// A compact proof of the votes on a proposal. It is the votes encoded with a
+/// bitmap of the voters and one aggregate signature of them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AggregateProof {
+    /// The votes of the proof.
+    pub votes: CompactVotes,
+    /// The aggregate signature of the votes.
+    pub signature: Vec<u8>,
+}
+
+impl AggregateProof {
+    /// A function to aggregate the votes on a proposal with their signatures,
+    /// such as the `lock_votes` of a `Commit`. The voters must be in the
+    /// authority list and every signature must be valid.
+    pub fn new<A: Aggregator + ?Sized>(
+        aggregator: &A,
+        authority_list: &[Node],
+        votes: &[(Vote, Vec<u8>)],
+    ) -> Result<Self, BftError> {
+        let compact = CompactVotes::from_votes(
+            authority_list,
+            &votes.iter().map(|(v, _)| v.clone()).collect::<Vec<_>>(),
+        )?;
+        let msg = vote_message(&votes[0].0);
+        let mut signatures = Vec::new();
+        for (vote, signature) in votes.iter() {
+            if !aggregator.verify(&msg, &vote.voter, signature) {
+                return Err(BftError::InvalidProof);
+            }
+            signatures.push(signature.as_slice());
+        }
+
+        Ok(AggregateProof {
+            votes: compact,
+            signature: aggregator
+                .aggregate(&signatures)
+                .ok_or(BftError::InvalidProof)?,
+        })
+    }
+
+    /// A function to verify the proof of a commit against the authority list of
+    /// `Status`. The votes must be precommits, the aggregate signature must be
+    /// valid and the voters must have more than 2/3 of the vote weight.
+    pub fn verify<A: Aggregator + ?Sized>(
+        &self,
+        aggregator: &A,
+        authority_list: &[Node],
+    ) -> Result<(), BftError> {
+        self.check(aggregator, authority_list, VoteType::Precommit, (0, 1))
+    }
+
+    /// A function to verify the proof of a commit on the fast path, whose votes
+    /// are the prevotes of round 0. The voters must reach the fast path
+    /// threshold `(numerator, denominator)` of the vote weight besides +2/3.
+    pub fn verify_fast<A: Aggregator + ?Sized>(
+        &self,
+        aggregator: &A,
+        authority_list: &[Node],
+        threshold: (u64, u64),
+    ) -> Result<(), BftError> {
+        self.check(aggregator, authority_list, VoteType::Prevote, threshold)
+    }
+
+    fn check<A: Aggregator + ?Sized>(
+        &self,
+        aggregator: &A,
+        authority_list: &[Node],
+        vote_type: VoteType,
+        (numerator, denominator): (u64, u64),
+    ) -> Result<(), BftError> {
+        let votes = &self.votes;
+        if votes.vote_type != vote_type {
+            return Err(BftError::InvalidProof);
+        }
+
+        let voters = votes.voters(authority_list)?;
+        let weight: u128 = voters.iter().map(|n| u128::from(n.vote_weight)).sum();
+        let total: u128 = authority_list
+            .iter()
+            .map(|n| u128::from(n.vote_weight))
+            .sum();
+        if weight * 3 <= total * 2
+            || weight * u128::from(denominator) < total * u128::from(numerator)
+        {
+            return Err(BftError::InvalidProof);
+        }
+
+        let msg = message(&votes.vote_type, votes.height, votes.round, &votes.proposal);
+        let addresses: Vec<&Address> = voters.iter().map(|n| &n.address).collect();
+        if !aggregator.verify_aggregate(&msg, &addresses, &self.signature) {
+            return Err(BftError::InvalidProof);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A toy scheme whose signature is the signer and the message, the
+    /// aggregate signature is the concatenation of them.
+    #[derive(Debug)]
+    struct Concat(Address);
+
+    fn sign_by(signer: &Address, msg: &[u8]) -> Vec<u8> {
+        let mut signature = signer.clone().into_vec();
+        signature.extend_from_slice(msg);
+        signature
+    }
+
+    impl Aggregator for Concat {
+        fn sign(&self, msg: &[u8]) -> Vec<u8> {
+            sign_by(&self.0, msg)
+        }
+
+        fn verify(&self, msg: &[u8], signer: &Address, signature: &[u8]) -> bool {
+            sign_by(signer, msg) == signature
+        }
+
+        fn aggregate(&self, signatures: &[&[u8]]) -> Option<Vec<u8>> {
+            Some(signatures.concat())
+        }
+
+        fn verify_aggregate(&self, msg: &[u8], signers: &[&Address], signature: &[u8]) -> bool {
+            let expect: Vec<u8> = signers.iter().flat_map(|s| sign_by(s, msg)).collect();
+            expect == signature
+        }
+    }
+
+    fn signed_vote(voter: u8, proposal: u8) -> (Vote, Vec<u8>) {
+        signed(VoteType::Precommit, voter, proposal)
+    }
+
+    fn signed(vote_type: VoteType, voter: u8, proposal: u8) -> (Vote, Vec<u8>) {
+        let vote = Vote {
+            vote_type,
+            height: 1,
+            round: 0,
+            proposal: Target::new(vec![proposal]),
+            voter: Address::new(vec![voter]),
+        };
+        let signature = Concat(vote.voter.clone()).sign(&vote_message(&vote));
+        (vote, signature)
+    }
+
+    #[test]
+    fn test_aggregate_proof() {
+        let authority_list: Vec<Node> = (0..10).map(|i| Node::new(Address::new(vec![i]))).collect();
+        let aggregator = Concat(Address::new(vec![0]));
+        let votes: Vec<_> = (2..9).map(|voter| signed_vote(voter, 7)).collect();
+
+        let proof = AggregateProof::new(&aggregator, &authority_list, &votes).unwrap();
+        assert_eq!(proof.verify(&aggregator, &authority_list), Ok(()));
+        assert_eq!(proof.votes.voters.count(), 7);
+
+        // a forged signer or a proof without a quorum is invalid
+        let mut forged = proof.clone();
+        forged.votes.voters.set(9);
+        assert_eq!(
+            forged.verify(&aggregator, &authority_list),
+            Err(BftError::InvalidProof)
+        );
+        let proof = AggregateProof::new(&aggregator, &authority_list, &votes[..6]).unwrap();
+        assert_eq!(
+            proof.verify(&aggregator, &authority_list),
+            Err(BftError::InvalidProof)
+        );
+
+        // the votes must be on one proposal and signed by the authorities
+        let mut mixed = votes.clone();
+        mixed.push(signed_vote(9, 8));
+        assert!(AggregateProof::new(&aggregator, &authority_list, &mixed).is_err());
+        let mut unknown = votes.clone();
+        unknown.push(signed_vote(10, 7));
+        assert!(AggregateProof::new(&aggregator, &authority_list, &unknown).is_err());
+        let mut duplicate = votes.clone();
+        duplicate.push(signed_vote(2, 7));
+        assert!(AggregateProof::new(&aggregator, &authority_list, &duplicate).is_err());
+
+        // a commit proof is of precommits, a fast path proof is of prevotes
+        // reaching the fast path threshold
+        let proof = AggregateProof::new(&aggregator, &authority_list, &votes).unwrap();
+        assert_eq!(
+            proof.verify_fast(&aggregator, &authority_list, (7, 10)),
+            Err(BftError::InvalidProof)
+        );
+        let prevotes: Vec<_> = (2..9)
+            .map(|voter| signed(VoteType::Prevote, voter, 7))
+            .collect();
+        let proof = AggregateProof::new(&aggregator, &authority_list, &prevotes).unwrap();
+        assert_eq!(
+            proof.verify(&aggregator, &authority_list),
+            Err(BftError::InvalidProof)
+        );
+        assert_eq!(
+            proof.verify_fast(&aggregator, &authority_list, (7, 10)),
+            Ok(())
+        );
+        assert_eq!(
+            proof.verify_fast(&aggregator, &authority_list, (3, 4)),
+            Err(BftError::InvalidProof)
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use super::Aggregator;
+use crate::types::Address;
+
+use bls12_381::{
+    hash_to_curve::{ExpandMsgXmd, HashToCurve},
+    pairing, G1Affine, G1Projective, G2Affine, G2Projective, Scalar,
+};
+use sha2::{Digest, Sha256, Sha512};
+use std::{convert::TryFrom, fmt};
+
+/// The domain separation tag of the proof-of-possession scheme.
+const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// An aggregator of BLS signatures on BLS12-381. The address of an authority is
+/// its compressed public key in G1, and a signature is a compressed point in
+/// G2. The signatures of one message add up to one signature of 96 bytes.
+///
+/// The public keys of the authority list must be checked with a proof of
+/// possession when they are registered, otherwise a rogue key can forge an
+/// aggregate signature.
+#[derive(Clone)]
+pub struct BlsAggregator {
+    secret: Scalar,
+    public: G1Affine,
+}
+
+impl fmt::Debug for BlsAggregator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlsAggregator({:?})", self.address())
+    }
+}
+
+impl BlsAggregator {
+    /// A function to create a new aggregator of the key derived from the seed.
+    /// The seed should have at least 32 bytes of entropy.
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let mut wide = [0u8; 64];
+        wide.copy_from_slice(&Sha512::digest(seed));
+        let secret = Scalar::from_bytes_wide(&wide);
+        BlsAggregator {
+            secret,
+            public: G1Affine::from(G1Affine::generator() * secret),
+        }
+    }
+
+    /// A function to get the address of the key, it is the public key.
+    pub fn address(&self) -> Address {
+        Address::new(self.public.to_compressed().to_vec())
+    }
+}
+
+fn hash_to_g2(msg: &[u8]) -> G2Affine {
+    G2Affine::from(<G2Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(msg, DST))
+}
+
+fn public_key(address: &Address) -> Option<G1Affine> {
+    let bytes = <[u8; 48]>::try_from(address.clone().into_vec().as_slice()).ok()?;
+    Option::<G1Affine>::from(G1Affine::from_compressed(&bytes))
+        .filter(|key| !bool::from(key.is_identity()))
+}
+
+fn signature(bytes: &[u8]) -> Option<G2Affine> {
+    let bytes = <[u8; 96]>::try_from(bytes).ok()?;
+    Option::from(G2Affine::from_compressed(&bytes))
+}
+
+impl Aggregator for BlsAggregator {
+    fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        G2Affine::from(hash_to_g2(msg) * self.secret)
+            .to_compressed()
+            .to_vec()
+    }
+
+    fn verify(&self, msg: &[u8], signer: &Address, sig: &[u8]) -> bool {
+        match (public_key(signer), signature(sig)) {
+            (Some(key), Some(sig)) => {
+                pairing(&G1Affine::generator(), &sig) == pairing(&key, &hash_to_g2(msg))
+            }
+            _ => false,
+        }
+    }
+
+    fn aggregate(&self, signatures: &[&[u8]]) -> Option<Vec<u8>> {
+        let mut sum = G2Projective::identity();
+        for sig in signatures.iter() {
+            sum += signature(sig)?;
+        }
+        Some(G2Affine::from(sum).to_compressed().to_vec())
+    }
+
+    fn verify_aggregate(&self, msg: &[u8], signers: &[&Address], sig: &[u8]) -> bool {
+        if signers.is_empty() {
+            return false;
+        }
+        let mut key = G1Projective::identity();
+        for signer in signers.iter() {
+            match public_key(signer) {
+                Some(k) => key += k,
+                None => return false,
+            }
+        }
+        match signature(sig) {
+            Some(sig) => {
+                pairing(&G1Affine::generator(), &sig)
+                    == pairing(&G1Affine::from(key), &hash_to_g2(msg))
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        aggregate::{vote_message, AggregateProof},
+        types::{Node, Target, Vote, VoteType},
+    };
+
+    #[test]
+    fn test_bls_proof() {
+        let keys: Vec<_> = (0..4u8)
+            .map(|i| BlsAggregator::from_seed(&[i; 32]))
+            .collect();
+        let authority_list: Vec<Node> = keys.iter().map(|k| Node::new(k.address())).collect();
+        let votes: Vec<_> = keys[1..]
+            .iter()
+            .map(|key| {
+                let vote = Vote {
+                    vote_type: VoteType::Precommit,
+                    height: 1,
+                    round: 0,
+                    proposal: Target::new(vec![7]),
+                    voter: key.address(),
+                };
+                let signature = key.sign(&vote_message(&vote));
+                (vote, signature)
+            })
+            .collect();
+
+        let proof = AggregateProof::new(&keys[0], &authority_list, &votes).unwrap();
+        assert_eq!(proof.signature.len(), 96);
+        assert_eq!(proof.verify(&keys[0], &authority_list), Ok(()));
+
+        let mut other = proof.clone();
+        other.votes.proposal = Target::new(vec![8]);
+        assert!(other.verify(&keys[0], &authority_list).is_err());
+        let mut forged = votes;
+        forged[0].1 = keys[0].sign(&vote_message(&forged[0].0));
+        assert!(AggregateProof::new(&keys[0], &authority_list, &forged).is_err());
+    }
+}
@@ -1,21 +1,32 @@
 use crate::{
+    clock::Clock,
+    config::{BftConfig, EmptyBlockPolicy},
+    error::BftError,
+    invariant::InvariantChecker,
     params::BftParams,
     rand::get_index,
-    timer::{TimeoutInfo, WaitTimer},
+    safety::{SafetyState, SafetyStore},
+    timer::{TimeoutInfo, TimerCmd, TimerId, WaitTimer},
     types::*,
-    voteset::{VoteCollector, VoteSet},
+    voteset::{RoundChangeCollector, VoteCollector, VoteSet},
     FromCore,
 };
 
-use crossbeam_channel::{select, unbounded, Receiver, RecvError, Sender};
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
 use log::{debug, error, info, trace, warn};
 use serde_derive::{Deserialize, Serialize};
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
+#[cfg(test)]
+mod simulation;
+
 pub(crate) const INIT_HEIGHT: u64 = 0;
+const DEFAULT_CHAIN_ID: ChainId = 0;
 const INIT_ROUND: u64 = 0;
 const PROPOSAL_TIMES_COEF: u64 = 10;
 const PRECOMMIT_BELOW_TWO_THIRDS: i8 = 0;
@@ -25,6 +36,9 @@ const PRECOMMIT_ON_PROPOSAL: i8 = 3;
 const TIMEOUT_RETRANSE_COEF: u32 = 15;
 const TIMEOUT_LOW_HEIGHT_MESSAGE_COEF: u32 = 300;
 const TIMEOUT_LOW_ROUND_MESSAGE_COEF: u32 = 300;
+// the proposals, the round changes and the chained votes of the rounds at most
+// this far ahead are saved
+pub(crate) const FUTURE_ROUND_WINDOW: u64 = 16;
 
 #[cfg(feature = "async_verify")]
 const VERIFY_AWAIT_COEF: u32 = 50;
@@ -70,9 +84,12 @@ impl Default for Step {
 /// BFT state message.
 pub(crate) struct Bft<T> {
     msg_sender: T,
-    msg_receiver: Receiver<CoreInput>,
-    timer_seter: Sender<TimeoutInfo>,
-    timer_notity: Receiver<TimeoutInfo>,
+    timer_seter: Sender<TimerCmd>,
+    // the last sequence number of the timers and the pending timer of each step
+    timer_seq: u64,
+    step_timers: HashMap<Step, TimerId>,
+    chain_id: ChainId,
+    running: bool,
 
     height: u64,
     round: u64,
@@ -80,16 +97,32 @@ pub(crate) struct Bft<T> {
     feed: Option<Feed>, // feed means the latest proposal given by auth at this height
     proposal: Option<Target>,
     votes: VoteCollector,
+    round_changes: RoundChangeCollector,
     lock_status: Option<LockStatus>,
+    // the proposals of the later rounds at this height, keyed by round
+    future_proposals: BTreeMap<u64, Proposal>,
+    // the proposals whose contents are in the application at this height
+    proposal_contents: HashSet<Target>,
+    requested_contents: HashSet<Target>,
+    // the commit waiting for the content of its proposal
+    pending_commit: Option<Commit>,
     last_commit_round: Option<u64>,
-    last_commit_proposal: Option<Target>,
+    // the commit sent at the last height, it collects the late precommits
+    // until the proposal of the next height
+    last_commit: Option<Commit>,
     height_filter: HashMap<Address, Instant>,
     round_filter: HashMap<Address, Instant>,
     authority_list: Vec<Address>,
     propose_weight: Vec<u64>,
     vote_weight_map: HashMap<Address, u64>,
+    // the vote weights of the past heights in the authority window
+    past_vote_weights: BTreeMap<u64, HashMap<Address, u64>>,
     htime: Instant,
     params: BftParams,
+    checker: InvariantChecker,
+    // the votes signed and the lock are saved to prevent double-signing
+    safety_store: Option<Box<dyn SafetyStore>>,
+    safety_state: SafetyState,
 
     #[cfg(feature = "async_verify")]
     verify_result: HashMap<Target, bool>,
@@ -100,67 +133,99 @@ where
     T: FromCore + Send + 'static,
 {
     /// A function to start a BFT state machine.
-    pub(crate) fn start(s: T, r: Receiver<CoreInput>, local_address: Address) {
+    pub(crate) fn start(s: T, r: Receiver<CoreInput>, local_address: Address, config: BftConfig) {
         // define message channel and timeout channel
         let (bft2timer, timer4bft) = unbounded();
         let (timer2bft, bft4timer) = unbounded();
 
         // start timer module.
-        thread::spawn(move || {
-            let timer = WaitTimer::new(timer2bft, timer4bft);
-            timer.start();
-        });
+        let timer = WaitTimer::new(timer2bft, timer4bft, Arc::clone(&config.clock));
+        thread::spawn(move || timer.start());
 
         // start main loop module.
-        let mut engine = Bft::initialize(s, r, bft2timer, bft4timer, local_address);
-        thread::spawn(move || {
-            let mut process_flag = false;
-            loop {
-                let mut get_timer_msg = Err(RecvError);
-                let mut get_msg = Err(RecvError);
-
-                select! {
-                    recv(engine.timer_notity) -> msg => get_timer_msg = msg,
-                    recv(engine.msg_receiver) -> msg => get_msg = msg,
-                }
-
-                if process_flag {
-                    if let Ok(ok_timer) = get_timer_msg {
-                        engine.timeout_process(&ok_timer);
-                    }
-
-                    if let Ok(ok_msg) = get_msg {
-                        if ok_msg == CoreInput::Pause {
-                            info!("BFT pause");
-                            process_flag = false;
-                        } else {
-                            engine.process(ok_msg);
-                        }
-                    }
-                } else if let Ok(ok_msg) = get_msg {
-                    if ok_msg == CoreInput::Start {
-                        info!("BFT go on running");
-                        process_flag = true;
+        let mut engine = Bft::initialize(s, bft2timer, local_address);
+        engine.apply_config(config);
+        thread::spawn(move || loop {
+            select! {
+                recv(bft4timer) -> msg => {
+                    if let Ok(ok_timer) = msg {
+                        engine.handle_timeout(&ok_timer);
                     }
                 }
+                recv(r) -> msg => match msg {
+                    Ok(ok_msg) => engine.handle_input(ok_msg),
+                    // the core is dropped
+                    Err(_) => return,
+                },
             }
         });
     }
 
+    /// A function to handle an input of the main loop. The inputs except `Start`
+    /// are dropped while the state machine is paused.
+    pub(crate) fn handle_input(&mut self, msg: CoreInput) {
+        if !self.running {
+            if msg == CoreInput::Start {
+                info!("BFT go on running");
+                self.running = true;
+            }
+            return;
+        }
+
+        if msg == CoreInput::Pause {
+            info!("BFT pause");
+            self.running = false;
+        } else if let Err(e) = self.process(msg) {
+            error!("Process message error {:?}", e);
+        }
+    }
+
+    /// A function to handle a timeout of the main loop.
+    pub(crate) fn handle_timeout(&mut self, info: &TimeoutInfo) {
+        if !self.running {
+            return;
+        }
+        if let Err(e) = self.timeout_process(info) {
+            error!("Process timeout {:?} error {:?}", info, e);
+        }
+    }
+
+    /// A function to apply the config to the state machine.
+    pub(crate) fn apply_config(&mut self, config: BftConfig) {
+        self.set_clock(config.clock);
+        self.params.report_evidence = config.report_evidence;
+        self.params.authority_window = config.authority_window;
+        self.params.empty_block = config.empty_block;
+        self.params.fast_commit = config.fast_commit;
+        if let Some((store, state)) = config.safety {
+            info!("Load safety state of height {:?}", state.height);
+            self.safety_store = Some(store);
+            self.safety_state = state;
+        }
+    }
+
+    /// A function to set the clock of the timers and the filters.
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.htime = clock.now();
+        self.params.clock = clock;
+    }
+
+    /// A function to set the chain ID of the timers, the timers of the
+    /// instances in a group are told apart by it.
+    pub(crate) fn set_chain_id(&mut self, chain_id: ChainId) {
+        self.chain_id = chain_id;
+    }
+
     #[cfg(not(feature = "async_verify"))]
-    fn initialize(
-        s: T,
-        r: Receiver<CoreInput>,
-        ts: Sender<TimeoutInfo>,
-        tn: Receiver<TimeoutInfo>,
-        local_address: Address,
-    ) -> Self {
+    pub(crate) fn initialize(s: T, ts: Sender<TimerCmd>, local_address: Address) -> Self {
         info!("BFT State Machine Launched.");
         Bft {
             msg_sender: s,
-            msg_receiver: r,
             timer_seter: ts,
-            timer_notity: tn,
+            timer_seq: 0,
+            step_timers: HashMap::new(),
+            chain_id: DEFAULT_CHAIN_ID,
+            running: false,
 
             height: INIT_HEIGHT,
             round: INIT_ROUND,
@@ -168,33 +233,38 @@ where
             feed: None,
             proposal: None,
             votes: VoteCollector::new(),
+            round_changes: RoundChangeCollector::new(),
             lock_status: None,
+            future_proposals: BTreeMap::new(),
+            proposal_contents: HashSet::new(),
+            requested_contents: HashSet::new(),
+            pending_commit: None,
             last_commit_round: None,
-            last_commit_proposal: None,
+            last_commit: None,
             authority_list: Vec::new(),
             propose_weight: Vec::new(),
             vote_weight_map: HashMap::new(),
+            past_vote_weights: BTreeMap::new(),
             htime: Instant::now(),
             height_filter: HashMap::new(),
             round_filter: HashMap::new(),
             params: BftParams::new(local_address),
+            checker: InvariantChecker::new(),
+            safety_store: None,
+            safety_state: SafetyState::default(),
         }
     }
 
     #[cfg(feature = "async_verify")]
-    fn initialize(
-        s: T,
-        r: Receiver<CoreInput>,
-        ts: Sender<TimeoutInfo>,
-        tn: Receiver<TimeoutInfo>,
-        local_address: Address,
-    ) -> Self {
+    pub(crate) fn initialize(s: T, ts: Sender<TimerCmd>, local_address: Address) -> Self {
         info!("BFT State Machine Launched.");
         Bft {
             msg_sender: s,
-            msg_receiver: r,
             timer_seter: ts,
-            timer_notity: tn,
+            timer_seq: 0,
+            step_timers: HashMap::new(),
+            chain_id: DEFAULT_CHAIN_ID,
+            running: false,
 
             height: INIT_HEIGHT,
             round: INIT_ROUND,
@@ -202,50 +272,171 @@ where
             feed: None,
             proposal: None,
             votes: VoteCollector::new(),
+            round_changes: RoundChangeCollector::new(),
             lock_status: None,
+            future_proposals: BTreeMap::new(),
+            proposal_contents: HashSet::new(),
+            requested_contents: HashSet::new(),
+            pending_commit: None,
             last_commit_round: None,
-            last_commit_proposal: None,
+            last_commit: None,
             authority_list: Vec::new(),
             propose_weight: Vec::new(),
             vote_weight_map: HashMap::new(),
+            past_vote_weights: BTreeMap::new(),
             htime: Instant::now(),
             height_filter: HashMap::new(),
             round_filter: HashMap::new(),
             params: BftParams::new(local_address),
+            checker: InvariantChecker::new(),
+            safety_store: None,
+            safety_state: SafetyState::default(),
             verify_result: HashMap::new(),
         }
     }
 
     #[inline]
-    fn set_timer(&self, duration: Duration, step: Step) {
+    fn set_timer(&mut self, duration: Duration, step: Step) {
         trace!("Set {:?} timer for {:?}", step, duration);
-        self.timer_seter
-            .send(TimeoutInfo {
-                timeval: Instant::now() + duration,
+        self.timer_seq = self.timer_seq.wrapping_add(1);
+        let id = TimerId {
+            chain_id: self.chain_id,
+            seq: self.timer_seq,
+        };
+        // a new timer of the step replaces the pending one
+        if let Some(pending) = self.step_timers.insert(step, id) {
+            self.send_timer_cmd(TimerCmd::Cancel(pending));
+        }
+        self.send_timer_cmd(TimerCmd::Set(
+            id,
+            TimeoutInfo {
+                timeval: self.params.clock.now() + duration,
+                chain_id: self.chain_id,
                 height: self.height,
                 round: self.round,
                 step,
-            })
-            .unwrap();
+            },
+        ));
+    }
+
+    #[inline]
+    fn send_timer_cmd(&self, cmd: TimerCmd) {
+        if self.timer_seter.send(cmd).is_err() {
+            error!("Send timer command error, the timer is stopped");
+        }
+    }
+
+    /// A function to cancel the timers of the earlier heights and rounds.
+    #[inline]
+    fn cancel_stale_timers(&mut self) {
+        self.step_timers.clear();
+        self.send_timer_cmd(TimerCmd::CancelBefore {
+            chain_id: self.chain_id,
+            height: self.height,
+            round: self.round,
+        });
     }
 
     #[inline]
     fn send_bft_msg(&mut self, msg: CoreOutput) {
-        self.msg_sender.send_msg(msg).unwrap();
+        match &msg {
+            CoreOutput::Vote(vote)
+            | CoreOutput::SendTo {
+                msg: PeerMsg::Vote(vote),
+                ..
+            } => self.checker.check_vote(vote),
+            _ => (),
+        }
+        // a lost message is tolerated as a network failure
+        if let Err(e) = self.msg_sender.send_msg(msg) {
+            error!("Send BFT message error {:?}", e);
+        }
+    }
+
+    #[inline]
+    fn total_weight(&self) -> u128 {
+        self.vote_weight_map.values().map(|w| u128::from(*w)).sum()
     }
 
     #[inline]
     fn cal_above_threshold(&self, count: u64) -> bool {
-        count * 3 > self.vote_weight_map.values().sum::<u64>() * 2
+        u128::from(count) * 3 > self.total_weight() * 2
     }
 
     #[inline]
     fn cal_all_vote(&self, count: u64) -> bool {
-        count == self.vote_weight_map.values().sum()
+        u128::from(count) == self.total_weight()
+    }
+
+    /// A function to check if the weight reaches the fast path threshold, which
+    /// is never below +2/3.
+    #[inline]
+    fn cal_fast_threshold(&self, count: u64) -> bool {
+        match self.params.fast_commit {
+            Some((numerator, denominator)) => {
+                u128::from(count) * u128::from(denominator)
+                    >= self.total_weight() * u128::from(numerator)
+                    && self.cal_above_threshold(count)
+            }
+            None => false,
+        }
+    }
+
+    /// A function to get the vote weights of the height, the ones of a past
+    /// height are kept only within the authority window.
+    #[inline]
+    fn vote_weights_of(&self, height: u64) -> Option<&HashMap<Address, u64>> {
+        if height == self.height {
+            Some(&self.vote_weight_map)
+        } else {
+            self.past_vote_weights.get(&height)
+        }
+    }
+
+    #[inline]
+    fn vote_weight_of(&self, height: u64, address: &Address) -> Option<u64> {
+        self.vote_weights_of(height)
+            .and_then(|weights| weights.get(address))
+            .cloned()
+    }
+
+    /// A function to tell whether the local node voted at the height.
+    #[inline]
+    fn is_voter_at(&self, height: u64) -> bool {
+        self.vote_weight_of(height, &self.params.address)
+            .is_some_and(|weight| weight > 0)
+    }
+
+    /// An observer has no vote weight, it follows the consensus without
+    /// proposing or voting.
+    #[inline]
+    fn is_observer(&self) -> bool {
+        self.vote_weight_map
+            .get(&self.params.address)
+            .map_or(true, |weight| *weight == 0)
+    }
+
+    #[inline]
+    fn local_weight(&self) -> Result<u64, BftError> {
+        self.vote_weight_map
+            .get(&self.params.address)
+            .cloned()
+            .ok_or(BftError::NotAuthority)
+    }
+
+    #[inline]
+    fn next_round(&self) -> Result<u64, BftError> {
+        self.round.checked_add(1).ok_or(BftError::Overflow)
     }
 
     #[inline]
     fn change_to_step(&mut self, step: Step) {
+        // the timer of a step is useless once the step is left
+        if step != self.step {
+            if let Some(pending) = self.step_timers.remove(&self.step) {
+                self.send_timer_cmd(TimerCmd::Cancel(pending));
+            }
+        }
         self.step = step;
     }
 
@@ -256,10 +447,20 @@ where
     }
 
     #[inline]
-    fn goto_next_round(&mut self) {
-        trace!("Goto next round {:?}", self.round + 1);
+    fn goto_next_round(&mut self) -> Result<(), BftError> {
+        let round = self.next_round()?;
+        trace!("Goto next round {:?}", round);
+        self.goto_round(round);
+        Ok(())
+    }
+
+    #[inline]
+    fn goto_round(&mut self, round: u64) {
         self.round_filter.clear();
-        self.round += 1;
+        self.round = round;
+        self.round_changes.remove_stale(round);
+        self.future_proposals = self.future_proposals.split_off(&round);
+        self.cancel_stale_timers();
     }
 
     #[inline]
@@ -268,7 +469,10 @@ where
         self.clean_filter();
         self.height = new_height;
         self.round = 0;
-        self.htime = Instant::now();
+        self.htime = self.params.clock.now();
+        self.checker.new_height(new_height);
+        self.restore_safety_state();
+        self.cancel_stale_timers();
     }
 
     #[inline]
@@ -276,55 +480,153 @@ where
         // clear prevote count needed when goto new height
         self.proposal = None;
         self.lock_status = None;
+        self.future_proposals.clear();
+        self.proposal_contents.clear();
+        self.requested_contents.clear();
+        self.pending_commit = None;
         self.votes.clear_prevote_count();
+        self.round_changes.clear();
         self.authority_list = Vec::new();
 
         #[cfg(feature = "async_verify")]
         self.verify_result.clear();
     }
 
-    fn retransmit_vote(&mut self, round: u64) {
+    /// A function to restore the lock and the round from the safety state once
+    /// the node goes to the height of the state, such as after a restart.
+    fn restore_safety_state(&mut self) {
+        if self.safety_store.is_none() || self.safety_state.height != self.height {
+            return;
+        }
+        if let Some(round) = self.safety_state.latest_round() {
+            self.round = round;
+        }
+        if let Some(lock) = self.safety_state.lock.clone() {
+            self.checker
+                .record_lock(self.height, lock.round, &lock.proposal);
+            self.proposal = Some(lock.proposal.clone());
+            self.lock_status = Some(lock);
+        }
         info!(
-            "Some nodes are at low height, retransmit votes of height {:?}, round {:?}",
-            self.height - 1,
-            round
+            "Restore safety state at height {:?}, round {:?}",
+            self.height, self.round
         );
+    }
 
-        debug!(
-            "Retransmit votes to proposal {:?}",
-            self.last_commit_proposal.clone().unwrap()
+    /// A function to check the vote against the safety state and save it before
+    /// it is sent. A vote conflicting with a signed one is refused.
+    fn sign_vote(&mut self, vote: &Vote) -> Result<(), BftError> {
+        if self.safety_store.is_none() {
+            return Ok(());
+        }
+        if let Some(signed) = self
+            .safety_state
+            .get_vote(vote.height, vote.round, &vote.vote_type)
+        {
+            if signed.proposal == vote.proposal {
+                return Ok(());
+            }
+            error!(
+                "Refuse to sign {:?} conflicting with the signed {:?}",
+                vote, signed
+            );
+            return Err(BftError::ConflictVote);
+        }
+        if vote.height < self.safety_state.height {
+            error!(
+                "Refuse to sign {:?} below the signed height {:?}",
+                vote, self.safety_state.height
+            );
+            return Err(BftError::ConflictVote);
+        }
+
+        let mut state = self.safety_state_of(vote.height);
+        state.votes.push(vote.clone());
+        self.save_safety_state(state)
+    }
+
+    fn save_lock(&mut self) -> Result<(), BftError> {
+        if self.safety_store.is_none() || self.height < self.safety_state.height {
+            return Ok(());
+        }
+        let mut state = self.safety_state_of(self.height);
+        state.lock = self.lock_status.clone();
+        self.save_safety_state(state)
+    }
+
+    fn safety_state_of(&self, height: u64) -> SafetyState {
+        if self.safety_state.height == height {
+            self.safety_state.clone()
+        } else {
+            SafetyState {
+                height,
+                ..SafetyState::default()
+            }
+        }
+    }
+
+    fn save_safety_state(&mut self, state: SafetyState) -> Result<(), BftError> {
+        if let Some(store) = self.safety_store.as_mut() {
+            if let Err(e) = store.save(&state) {
+                error!("Save safety state error {:?}", e);
+                return Err(BftError::SafetyStoreErr);
+            }
+        }
+        self.safety_state = state;
+        Ok(())
+    }
+
+    fn retransmit_vote(&mut self, height: u64, round: u64, to: Address) {
+        info!(
+            "Node {:?} is at low height, retransmit votes of height {:?}, round {:?}",
+            to, height, round
         );
 
-        self.send_bft_msg(CoreOutput::Vote(Vote {
-            vote_type: VoteType::Prevote,
-            height: self.height - 1,
-            round,
-            proposal: self.last_commit_proposal.clone().unwrap(),
-            voter: self.params.clone().address,
-        }));
+        for vote_type in [VoteType::Prevote, VoteType::Precommit].iter() {
+            // only retransmit the votes that have been sent, a vote never
+            // signed in the round is not made up for the node
+            if let Some(proposal) = self.sent_vote(height, round, vote_type.clone()) {
+                debug!("Retransmit {:?} to proposal {:?}", vote_type, proposal);
+                self.send_bft_msg(CoreOutput::SendTo {
+                    to: to.clone(),
+                    msg: PeerMsg::Vote(Vote {
+                        vote_type: vote_type.clone(),
+                        height,
+                        round,
+                        proposal,
+                        voter: self.params.address.clone(),
+                    }),
+                });
+            }
+        }
+    }
 
-        self.send_bft_msg(CoreOutput::Vote(Vote {
-            vote_type: VoteType::Precommit,
-            height: self.height - 1,
-            round,
-            proposal: self.last_commit_proposal.clone().unwrap(),
-            voter: self.params.clone().address,
-        }));
+    /// A function to get the vote that the node has sent at the height, round
+    /// and vote type, either in the vote collector or in the safety state.
+    fn sent_vote(&mut self, height: u64, round: u64, vote_type: VoteType) -> Option<Target> {
+        if let Some(proposal) =
+            self.votes
+                .get_vote(height, round, vote_type.clone(), &self.params.address)
+        {
+            return Some(proposal);
+        }
+        self.safety_state
+            .get_vote(height, round, &vote_type)
+            .map(|vote| vote.proposal.clone())
     }
 
     fn determine_height_filter(&mut self, sender: Address) -> bool {
         if let Some(ins) = self.height_filter.get(&sender) {
             // had received retransmit message from the address
-            if (Instant::now() - *ins)
+            if self.params.clock.now().saturating_duration_since(*ins)
                 > self.params.timer.get_prevote() * TIMEOUT_LOW_HEIGHT_MESSAGE_COEF
             {
                 return true;
             }
         } else {
             // never recvive retransmit message from the address
-            self.height_filter
-                .entry(sender)
-                .or_insert_with(Instant::now);
+            let now = self.params.clock.now();
+            self.height_filter.entry(sender).or_insert(now);
             return true;
         }
         false
@@ -333,32 +635,32 @@ where
     fn determine_round_filter(&mut self, sender: Address) -> bool {
         if let Some(ins) = self.round_filter.get(&sender) {
             // had received retransmit message from the address
-            if (Instant::now() - *ins)
+            if self.params.clock.now().saturating_duration_since(*ins)
                 > self.params.timer.get_prevote() * TIMEOUT_LOW_ROUND_MESSAGE_COEF
             {
                 return true;
             }
         } else {
             // never recvive retransmit message from the address
-            self.round_filter.entry(sender).or_insert_with(Instant::now);
+            let now = self.params.clock.now();
+            self.round_filter.entry(sender).or_insert(now);
             return true;
         }
         false
     }
 
-    fn is_proposer(&self) -> bool {
+    fn is_proposer(&mut self) -> Result<bool, BftError> {
         if self.authority_list.is_empty() {
             error!("The Authority List is Empty!");
-            return false;
+            return Ok(false);
         }
 
-        let nonce = self.height + self.round;
-        if self.params.address == self.authority_list[get_index(nonce, &self.propose_weight)] {
+        if Some(&self.params.address) == self.proposer_of(self.round)? {
             info!(
                 "Become proposer at height {:?}, round {:?}",
                 self.height, self.round
             );
-            return true;
+            return Ok(true);
         }
 
         // if is not proposer, goto step proposewait
@@ -372,105 +674,283 @@ where
             self.params.timer.get_propose() * 2u32.pow(coef as u32),
             Step::ProposeWait,
         );
-        false
+        Ok(false)
     }
 
-    fn try_transmit_proposal(&mut self) -> bool {
-        if self.lock_status.is_none()
-            && (self.feed.is_none() || self.feed.clone().unwrap().height != self.height)
-        {
-            // if a proposer find there is no proposal nor lock, goto step proposewait
-            info!("The lock status is none and feed is mismatched.");
-            let coef = if self.round > PROPOSAL_TIMES_COEF {
-                PROPOSAL_TIMES_COEF
-            } else {
-                self.round
-            };
+    /// A function to get the proposer of the round at the current height.
+    fn proposer_of(&self, round: u64) -> Result<Option<&Address>, BftError> {
+        self.proposer_at(self.height, round)
+    }
 
-            self.set_timer(
-                self.params.timer.get_propose() * 2u32.pow(coef as u32),
-                Step::ProposeWait,
-            );
-            return false;
+    /// A function to get the proposer of the height and round with the current
+    /// authorities.
+    fn proposer_at(&self, height: u64, round: u64) -> Result<Option<&Address>, BftError> {
+        if self.authority_list.is_empty() {
+            return Ok(None);
         }
+        let nonce = height.wrapping_add(round);
+        let index = get_index(nonce, &self.propose_weight).ok_or(BftError::InvalidAuthority)?;
+        Ok(self.authority_list.get(index))
+    }
 
-        let msg = if self.lock_status.is_some() {
+    fn try_transmit_proposal(&mut self) -> bool {
+        let msg = if let Some(lock) = self.lock_status.clone() {
             // if is locked, boradcast the lock proposal
             trace!(
                 "Proposal at height {:?}, round {:?}, is {:?}",
                 self.height,
                 self.round,
-                self.lock_status.clone().unwrap().proposal
+                lock.proposal
             );
 
-            let tmp = self.lock_status.clone().unwrap();
             CoreOutput::Proposal(Proposal {
                 height: self.height,
                 round: self.round,
-                content: tmp.proposal,
-                lock_round: Some(tmp.round),
-                lock_votes: tmp.votes,
+                content: lock.proposal,
+                lock_round: Some(lock.round),
+                lock_votes: lock.votes,
                 proposer: self.params.address.clone(),
+                parent_qc: None,
             })
-        } else {
+        } else if let Some(proposal) = self
+            .fast_path_lock()
+            .filter(|proposal| self.has_content(proposal))
+        {
+            // propose the proposal which may be committed on the fast path
+            self.proposal = Some(proposal.clone());
+            trace!(
+                "Proposal at height {:?}, round {:?}, is {:?}",
+                self.height,
+                self.round,
+                proposal
+            );
+
+            CoreOutput::Proposal(Proposal {
+                height: self.height,
+                round: self.round,
+                content: proposal,
+                lock_round: None,
+                lock_votes: Vec::new(),
+                proposer: self.params.address.clone(),
+                parent_qc: None,
+            })
+        } else if let Some(feed) = self.feed.clone().filter(|f| f.height == self.height) {
             // if is not locked, transmit the cached proposal
-            self.proposal = Some(self.feed.clone().unwrap().proposal);
+            self.proposal = Some(feed.proposal.clone());
             trace!(
                 "Proposal at height {:?}, round {:?}, is {:?}",
                 self.height,
                 self.round,
-                self.proposal.clone().unwrap()
+                feed.proposal
+            );
+
+            CoreOutput::Proposal(Proposal {
+                height: self.height,
+                round: self.round,
+                content: feed.proposal,
+                lock_round: None,
+                lock_votes: Vec::new(),
+                proposer: self.params.address.clone(),
+                parent_qc: None,
+            })
+        } else if let Some(empty) = self.empty_proposal() {
+            // propose the empty block supplied by the application
+            self.save_content(self.height, empty.clone());
+            self.proposal = Some(empty.clone());
+            trace!(
+                "Empty proposal at height {:?}, round {:?}, is {:?}",
+                self.height,
+                self.round,
+                empty
             );
 
             CoreOutput::Proposal(Proposal {
                 height: self.height,
                 round: self.round,
-                content: self.proposal.clone().unwrap(),
+                content: empty,
                 lock_round: None,
                 lock_votes: Vec::new(),
                 proposer: self.params.address.clone(),
+                parent_qc: None,
             })
+        } else {
+            // if a proposer find there is no proposal nor lock, goto step proposewait
+            info!("The lock status is none and feed is mismatched.");
+            let coef = if self.round > PROPOSAL_TIMES_COEF {
+                PROPOSAL_TIMES_COEF
+            } else {
+                self.round
+            };
+
+            let timeout = self.params.timer.get_propose() * 2u32.pow(coef as u32);
+            match self.params.empty_block {
+                EmptyBlockPolicy::Skip => {}
+                EmptyBlockPolicy::WaitFor(max) => {
+                    self.set_timer(timeout.min(max), Step::ProposeWait);
+                }
+                _ => self.set_timer(timeout, Step::ProposeWait),
+            }
+            return false;
         };
         info!(
             "Transmit proposal at height {:?}, round {:?}",
             self.height, self.round
         );
+        // the last commit is final once the next height is proposed
+        self.last_commit = None;
         self.send_bft_msg(msg);
         true
     }
 
-    fn handle_proposal(&mut self, proposal: Proposal) -> Option<Proposal> {
-        if proposal.height == self.height - 1 {
-            if self.last_commit_round.is_some() && proposal.round >= self.last_commit_round.unwrap()
-            {
-                // deal with height fall behind one, round ge last commit round
-                self.retransmit_vote(proposal.round);
+    /// A function to get the empty target to propose without a feed, if the
+    /// policy and the application allow an empty block at the height.
+    fn empty_proposal(&mut self) -> Option<Target> {
+        match self.params.empty_block.clone() {
+            EmptyBlockPolicy::Propose(empty) if self.msg_sender.allow_empty_block(self.height) => {
+                Some(empty)
             }
-            None
+            _ => None,
+        }
+    }
+
+    fn handle_proposal(&mut self, proposal: Proposal) -> Result<Option<Proposal>, BftError> {
+        if Some(proposal.height) == self.height.checked_sub(1) {
+            if let Some(last_commit_round) = self.last_commit_round {
+                if proposal.round >= last_commit_round
+                    && self.is_voter_at(proposal.height)
+                    && self
+                        .vote_weight_of(proposal.height, &proposal.proposer)
+                        .is_some()
+                {
+                    // deal with height fall behind one, round ge last commit round
+                    self.retransmit_vote(proposal.height, proposal.round, proposal.proposer);
+                }
+            }
+            Ok(None)
         } else if proposal.height != self.height || proposal.round < self.round {
-            // bft-rs lib only handle the proposals with same round, the proposals
-            // with higher round should be saved outside
             warn!(
                 "Receive mismatched proposal! \nThe proposal height is {:?}, \
                  round is {:?}, self height is {:?}, round is {:?}, the proposal is {:?} !",
                 proposal.height, proposal.round, self.height, self.round, proposal.content
             );
-            None
+            Ok(None)
+        } else if self.proposer_of(proposal.round)? != Some(&proposal.proposer) {
+            self.reject_proposal(proposal, RejectReason::WrongProposer);
+            Ok(None)
+        } else if let Err(reason) = self.check_polc(&proposal) {
+            self.reject_proposal(proposal, reason);
+            Ok(None)
         } else {
-            Some(proposal)
+            Ok(Some(proposal))
+        }
+    }
+
+    fn reject_proposal(&mut self, proposal: Proposal, reason: RejectReason) {
+        warn!(
+            "Reject the proposal at height {:?}, round {:?}, from {:?}, for {:?}",
+            proposal.height, proposal.round, proposal.proposer, reason
+        );
+        if self.params.report_evidence {
+            self.send_bft_msg(CoreOutput::Evidence(Evidence { reason, proposal }));
+        }
+    }
+
+    /// A function to check the PoLC of a proposal. The lock votes must be
+    /// prevotes on the proposal at its height and lock round, from distinct
+    /// authorities with more than 2/3 of the vote weight. The signatures of the
+    /// votes are checked by the application or the transport before the
+    /// proposal is input.
+    fn check_polc(&self, proposal: &Proposal) -> Result<(), RejectReason> {
+        let lock_round = match proposal.lock_round {
+            Some(lock_round) => lock_round,
+            None => return Ok(()),
+        };
+        if lock_round > proposal.round {
+            return Err(RejectReason::InvalidLockRound);
+        }
+
+        let mut voters = HashSet::new();
+        let mut weight = 0u64;
+        for vote in proposal.lock_votes.iter() {
+            if vote.vote_type != VoteType::Prevote
+                || vote.height != proposal.height
+                || vote.round != lock_round
+                || vote.proposal != proposal.content
+            {
+                return Err(RejectReason::MismatchedLockVote);
+            }
+            match self.vote_weight_map.get(&vote.voter) {
+                Some(w) if *w > 0 => weight = weight.saturating_add(*w),
+                _ => return Err(RejectReason::UnknownLockVoter),
+            }
+            if !voters.insert(&vote.voter) {
+                return Err(RejectReason::DuplicateLockVote);
+            }
+        }
+        if !self.cal_above_threshold(weight) {
+            return Err(RejectReason::InsufficientLockWeight);
+        }
+        Ok(())
+    }
+
+    fn accept_proposal(&mut self, proposal: Proposal) -> Result<(), BftError> {
+        self.set_proposal(proposal)?;
+        if self.step == Step::ProposeWait && !self.is_observer() {
+            self.change_to_step(Step::Prevote);
+            self.transmit_prevote()?;
+            if self.try_fast_commit(self.round)? {
+                return Ok(());
+            }
+            if self.check_prevote_count()? {
+                self.change_to_step(Step::PrevoteWait);
+            }
+        }
+        Ok(())
+    }
+
+    /// A function to save a checked proposal of a later round at this height,
+    /// it is accepted once the node enters the round. Only the first proposal
+    /// of a round within the window is saved.
+    fn save_future_proposal(&mut self, proposal: Proposal) {
+        if proposal.round - self.round > FUTURE_ROUND_WINDOW {
+            warn!(
+                "Drop the proposal of round {:?}, self round is {:?}",
+                proposal.round, self.round
+            );
+            return;
+        }
+        debug!(
+            "Save the proposal of round {:?} at height {:?}",
+            proposal.round, self.height
+        );
+        self.future_proposals
+            .entry(proposal.round)
+            .or_insert(proposal);
+    }
+
+    /// A function to accept the saved proposal of the current round, the ones
+    /// of the earlier rounds are dropped.
+    fn take_future_proposal(&mut self) -> Result<(), BftError> {
+        self.future_proposals = self.future_proposals.split_off(&self.round);
+        if let Some(proposal) = self.future_proposals.remove(&self.round) {
+            self.accept_proposal(proposal)?;
         }
+        Ok(())
     }
 
-    fn set_proposal(&mut self, proposal: Proposal) {
+    fn set_proposal(&mut self, proposal: Proposal) -> Result<(), BftError> {
         info!(
             "Receive a proposal at height {:?}, round {:?}, from {:?}",
             self.height, proposal.round, proposal.proposer
         );
+        self.last_commit = None;
 
-        if proposal.lock_round.is_some()
-            && (self.lock_status.is_none()
-                || self.lock_status.clone().unwrap().round <= proposal.lock_round.unwrap())
-        {
+        let later_lock_round = match (proposal.lock_round, self.lock_status.as_ref()) {
+            (Some(lock_round), Some(lock)) if lock.round > lock_round => None,
+            (lock_round, _) => lock_round,
+        };
+
+        if let Some(lock_round) = later_lock_round {
             // receive a proposal with a later PoLC
             debug!(
                 "Receive a proposal with the PoLC that proposal is {:?}, lock round is {:?}, lock votes are {:?}",
@@ -480,16 +960,22 @@ where
             );
 
             if self.round < proposal.round {
-                self.round_filter.clear();
-                self.round = proposal.round;
+                self.goto_round(proposal.round);
+                // the proposal replaces the saved one of the round
+                self.future_proposals.remove(&proposal.round);
             }
 
             self.proposal = Some(proposal.content.clone());
             self.lock_status = Some(LockStatus {
                 proposal: proposal.content,
-                round: proposal.lock_round.unwrap(),
+                round: lock_round,
                 votes: proposal.lock_votes,
             });
+            if let Some(lock) = self.lock_status.as_ref() {
+                self.checker
+                    .record_lock(self.height, lock.round, &lock.proposal);
+            }
+            self.save_lock()?;
         } else if proposal.lock_votes.is_empty()
             && self.lock_status.is_none()
             && proposal.round == self.round
@@ -502,18 +988,26 @@ where
             self.proposal = Some(proposal.content);
         } else {
             debug!("Receive a proposal that the PoLC is earlier than mine");
-            return;
         }
+        Ok(())
     }
 
-    fn transmit_prevote(&mut self) {
-        let prevote = if let Some(lock_proposal) = self.lock_status.clone() {
-            lock_proposal.proposal
-        } else if let Some(proposal) = self.proposal.clone() {
-            proposal
-        } else {
-            Target::new(vec![])
-        };
+    fn transmit_prevote(&mut self) -> Result<(), BftError> {
+        let weight = self.local_weight()?;
+        let prevote =
+            if let Some(prevote) = self.sent_vote(self.height, self.round, VoteType::Prevote) {
+                // the prevote of the round has been sent or signed before a
+                // restart, retransmit it
+                prevote
+            } else if let Some(lock_proposal) = self.lock_status.clone() {
+                lock_proposal.proposal
+            } else if let Some(proposal) = self.fast_path_lock() {
+                proposal
+            } else if let Some(proposal) = self.proposal.clone() {
+                proposal
+            } else {
+                Target::new(vec![])
+            };
 
         trace!(
             "Transmit prevote at height {:?}, round {:?}",
@@ -529,9 +1023,8 @@ where
             voter: self.params.address.clone(),
         };
 
-        let _ = self
-            .votes
-            .add(vote.clone(), self.vote_weight_map[&self.params.address]);
+        self.sign_vote(&vote)?;
+        let _ = self.votes.add(vote.clone(), weight);
         let msg = CoreOutput::Vote(vote);
         debug!("Prevote to {:?}", prevote);
         self.send_bft_msg(msg);
@@ -539,66 +1032,95 @@ where
             self.params.timer.get_prevote() * TIMEOUT_RETRANSE_COEF,
             Step::Prevote,
         );
+        Ok(())
     }
 
-    fn try_save_vote(&mut self, vote: Vote) -> bool {
+    fn try_save_vote(&mut self, vote: Vote) -> Result<bool, BftError> {
         info!(
             "Receive a {:?} vote of height {:?}, round {:?}, to {:?}, from {:?}",
             vote.vote_type, vote.height, vote.round, vote.proposal, vote.voter
         );
 
-        if self.vote_weight_map.get(&vote.voter).is_none() {
-            error!("Lose vote weight of node {:?}", vote.voter);
-            return false;
+        if vote.voter == self.params.address {
+            // the votes of the local node are saved when they are sent, a
+            // received one is an echo or a forgery
+            warn!("Receive a vote of the local node");
+            return Ok(false);
         }
 
-        if vote.height == self.height - 1 {
-            if self.last_commit_round.is_some() && vote.round >= self.last_commit_round.unwrap() {
+        // weigh the vote with the authorities of its own height
+        let weight = if let Some(weight) = self.vote_weight_of(vote.height, &vote.voter) {
+            weight
+        } else {
+            error!(
+                "Lose vote weight of node {:?} at height {:?}",
+                vote.voter, vote.height
+            );
+            return Ok(false);
+        };
+
+        if Some(vote.height) == self.height.checked_sub(1) {
+            if let Some(last_commit_round) = self.last_commit_round {
                 // deal with height fall behind one, round ge last commit round
-                if self.determine_height_filter(vote.voter.clone()) {
-                    self.retransmit_vote(vote.round);
+                if vote.round >= last_commit_round
+                    && self.is_voter_at(vote.height)
+                    && self.determine_height_filter(vote.voter.clone())
+                {
+                    self.retransmit_vote(vote.height, vote.round, vote.voter);
                 }
             }
-            return false;
+            return Ok(false);
         } else if vote.height == self.height && self.round != 0 && vote.round == self.round - 1 {
             // deal with equal height, round fall behind
-            if self.determine_round_filter(vote.voter.clone()) {
-                info!("Some nodes fall behind, send nil vote to help them pursue");
-                self.send_bft_msg(CoreOutput::Vote(Vote {
-                    vote_type: VoteType::Precommit,
-                    height: vote.height,
-                    round: vote.round,
-                    proposal: Target::new(vec![]),
-                    voter: self.params.clone().address,
-                }));
+            if !self.is_observer() && self.determine_round_filter(vote.voter.clone()) {
+                // retransmit the precommit of the round if it has been sent
+                if let Some(proposal) = self.sent_vote(vote.height, vote.round, VoteType::Precommit)
+                {
+                    info!(
+                        "Node {:?} falls behind, send precommit to help it pursue",
+                        vote.voter
+                    );
+                    self.send_bft_msg(CoreOutput::SendTo {
+                        to: vote.voter.clone(),
+                        msg: PeerMsg::Vote(Vote {
+                            vote_type: VoteType::Precommit,
+                            height: vote.height,
+                            round: vote.round,
+                            proposal,
+                            voter: self.params.address.clone(),
+                        }),
+                    });
+                }
             }
-            return false;
-        } else if vote.height == self.height
-            && vote.round >= self.round
-            && self
-                .votes
-                .add(vote.clone(), self.vote_weight_map[&vote.voter])
-        {
+        }
+
+        // save the votes of every round at the height, the precommits of an
+        // earlier round may still lead to a commit
+        if vote.height == self.height && self.votes.add(vote.clone(), weight) {
             trace!("Add the vote successfully");
-            return true;
+            return Ok(true);
         }
         trace!("Receive a saved vote");
-        false
+        Ok(false)
     }
 
-    fn check_prevote_count(&mut self) -> bool {
-        let mut flag = false;
-        for (round, prevote_count) in self.votes.prevote_count.iter() {
-            if self.cal_above_threshold(*prevote_count) && *round >= self.round {
-                flag = true;
-                if self.round < *round {
-                    self.round_filter.clear();
-                    self.round = *round;
-                }
+    fn check_prevote_count(&mut self) -> Result<bool, BftError> {
+        let round = self
+            .votes
+            .prevote_count
+            .iter()
+            .filter(|(round, count)| **round >= self.round && self.cal_above_threshold(**count))
+            .map(|(round, _)| *round)
+            .max();
+        match round {
+            Some(round) if round > self.round => {
+                // go to the latest round with +2/3 prevotes, and take the
+                // proposal saved for it
+                self.goto_round(round);
+                self.take_future_proposal()?;
             }
-        }
-        if !flag {
-            return false;
+            Some(_) => (),
+            None => return Ok(false),
         }
         info!(
             "Receive over 2/3 prevote at height {:?}, round {:?}",
@@ -617,8 +1139,11 @@ where
 
             for (hash, count) in &prevote_set.votes_by_proposal {
                 if self.cal_above_threshold(*count) {
-                    if self.lock_status.is_some()
-                        && self.lock_status.clone().unwrap().round < self.round
+                    let round = self.round;
+                    if self
+                        .lock_status
+                        .as_ref()
+                        .is_some_and(|lock| lock.round < round)
                     {
                         if hash.is_nil() {
                             // receive +2/3 prevote to nil, clean lock info
@@ -627,16 +1152,16 @@ where
                                 self.height,
                                 self.round
                             );
-                            self.clean_polc();
+                            self.clean_polc()?;
                             self.proposal = None;
                         } else {
                             // receive a later PoLC, update lock info
-                            self.set_polc(&hash, &prevote_set, VoteType::Prevote);
+                            self.set_polc(&hash, &prevote_set, VoteType::Prevote)?;
                         }
                     }
                     if self.lock_status.is_none() && !hash.is_nil() {
                         // receive a PoLC, lock the proposal
-                        self.set_polc(&hash, &prevote_set, VoteType::Prevote);
+                        self.set_polc(&hash, &prevote_set, VoteType::Prevote)?;
                     }
                     tv = Duration::new(0, 0);
                     break;
@@ -645,18 +1170,18 @@ where
             if self.step == Step::Prevote {
                 self.set_timer(tv, Step::PrevoteWait);
             }
-            return true;
+            return Ok(true);
         }
-        false
+        Ok(false)
     }
 
     #[cfg(feature = "async_verify")]
-    fn check_verify(&mut self) -> VerifyResult {
+    fn check_verify(&mut self) -> Result<VerifyResult, BftError> {
         if let Some(lock) = self.lock_status.clone() {
             let prop = lock.proposal;
-            if self.verify_result.contains_key(&prop) {
-                if *self.verify_result.get(&prop).unwrap() {
-                    return VerifyResult::Approved;
+            if let Some(is_pass) = self.verify_result.get(&prop).cloned() {
+                if is_pass {
+                    return Ok(VerifyResult::Approved);
                 } else {
                     if let Some(feed) = self.feed.clone() {
                         // if feed eq proposal, clean it
@@ -665,25 +1190,31 @@ where
                         }
                     }
                     // clean save info
-                    self.clean_polc();
-                    return VerifyResult::Failed;
+                    self.clean_polc()?;
+                    return Ok(VerifyResult::Failed);
                 }
             } else {
                 let tv = self.params.timer.get_prevote() * VERIFY_AWAIT_COEF;
                 self.set_timer(tv, Step::VerifyWait);
-                return VerifyResult::Undetermined;
+                return Ok(VerifyResult::Undetermined);
             }
         }
-        VerifyResult::Approved
+        Ok(VerifyResult::Approved)
     }
 
-    fn transmit_precommit(&mut self) {
-        let precommit = if let Some(lock_proposal) = self.lock_status.clone() {
-            lock_proposal.proposal
-        } else {
-            self.proposal = None;
-            Target::new(vec![])
-        };
+    fn transmit_precommit(&mut self) -> Result<(), BftError> {
+        let weight = self.local_weight()?;
+        let precommit =
+            if let Some(precommit) = self.sent_vote(self.height, self.round, VoteType::Precommit) {
+                // the precommit of the round has been sent or signed before a
+                // restart, retransmit it
+                precommit
+            } else if let Some(lock_proposal) = self.lock_status.clone() {
+                lock_proposal.proposal
+            } else {
+                self.proposal = None;
+                Target::new(vec![])
+            };
 
         trace!(
             "Transmit precommit at height {:?}, round {:?}",
@@ -699,9 +1230,8 @@ where
             voter: self.params.address.clone(),
         };
 
-        let _ = self
-            .votes
-            .add(vote.clone(), self.vote_weight_map[&self.params.address]);
+        self.sign_vote(&vote)?;
+        let _ = self.votes.add(vote.clone(), weight);
         let msg = CoreOutput::Vote(vote);
 
         debug!("Precommit to {:?}", precommit);
@@ -710,9 +1240,10 @@ where
             self.params.timer.get_precommit() * TIMEOUT_RETRANSE_COEF,
             Step::Precommit,
         );
+        Ok(())
     }
 
-    fn check_precommit_count(&mut self) -> i8 {
+    fn check_precommit_count(&mut self) -> Result<i8, BftError> {
         if let Some(precommit_set) =
             self.votes
                 .get_voteset(self.height, self.round, VoteType::Precommit)
@@ -723,7 +1254,7 @@ where
                 self.params.timer.get_precommit()
             };
             if !self.cal_above_threshold(precommit_set.count) {
-                return PRECOMMIT_BELOW_TWO_THIRDS;
+                return Ok(PRECOMMIT_BELOW_TWO_THIRDS);
             }
 
             info!(
@@ -734,11 +1265,11 @@ where
             for (hash, count) in &precommit_set.votes_by_proposal {
                 if self.cal_above_threshold(*count) {
                     if hash.is_nil() {
-                        info!("Reach nil consensus, goto next round {:?}", self.round + 1);
-                        return PRECOMMIT_ON_NIL;
+                        info!("Reach nil consensus at round {:?}", self.round);
+                        return Ok(PRECOMMIT_ON_NIL);
                     } else {
-                        self.set_polc(&hash, &precommit_set, VoteType::Precommit);
-                        return PRECOMMIT_ON_PROPOSAL;
+                        self.set_polc(&hash, &precommit_set, VoteType::Precommit)?;
+                        return Ok(PRECOMMIT_ON_PROPOSAL);
                     }
                 }
             }
@@ -746,37 +1277,323 @@ where
                 self.set_timer(tv, Step::PrecommitWait);
             }
         }
-        PRECOMMIT_ON_NOTHING
+        Ok(PRECOMMIT_ON_NOTHING)
+    }
+
+    fn proc_commit(&mut self) -> Result<(), BftError> {
+        // a commit always follows a PoLC of precommits
+        let result = self.lock_status.clone().ok_or(BftError::Unreachable)?;
+        self.commit(self.round, result.proposal, result.votes, false);
+        Ok(())
     }
 
-    fn proc_commit(&mut self) {
-        let result = self.lock_status.clone().expect("No lock when commit!");
-        self.send_bft_msg(CoreOutput::Commit(Commit {
+    /// A function to commit the proposal. If the content of the proposal is not
+    /// in the application, request it and hold the commit in `Commit` step. A
+    /// commit on the fast path lists the prevotes instead of the precommits.
+    fn commit(&mut self, round: u64, proposal: Target, lock_votes: Vec<Vote>, fast_path: bool) {
+        self.change_to_step(Step::Commit);
+        let vote_type = if fast_path {
+            VoteType::Prevote
+        } else {
+            VoteType::Precommit
+        };
+        let precommit_set = self
+            .votes
+            .get_voteset(self.height, round, vote_type.clone())
+            .unwrap_or_else(VoteSet::new);
+        // list the precommits and the absent authorities in authority order
+        let mut precommits = Vec::new();
+        let mut absent = Vec::new();
+        for address in self.authority_list.iter() {
+            if let Some(target) = precommit_set.votes_by_sender.get(address) {
+                precommits.push(Vote {
+                    vote_type: vote_type.clone(),
+                    height: self.height,
+                    round,
+                    proposal: target.clone(),
+                    voter: address.clone(),
+                });
+            } else if self.vote_weight_map.get(address).is_some_and(|w| *w > 0) {
+                absent.push(address.clone());
+            }
+        }
+
+        let commit = Commit {
             height: self.height,
-            round: self.round,
-            proposal: result.proposal.clone(),
-            lock_votes: self.lock_status.clone().unwrap().votes,
+            round,
+            precommit_weight: precommit_set
+                .votes_by_proposal
+                .get(&proposal)
+                .cloned()
+                .unwrap_or(0),
+            proposal,
+            lock_votes,
             address: self.params.clone().address,
-        }));
+            precommits,
+            absent,
+            total_weight: u64::try_from(self.total_weight()).unwrap_or(u64::MAX),
+            rounds: round.saturating_add(1),
+            elapsed: 0,
+            fast_path,
+        };
+
+        if !self.has_content(&commit.proposal) {
+            info!(
+                "Hold the commit of {:?} at height {:?} until the content arrives",
+                commit.proposal, self.height
+            );
+            self.request_content(round, &commit.proposal);
+            self.pending_commit = Some(commit);
+            return;
+        }
+        self.send_commit(commit);
+    }
 
+    fn send_commit(&mut self, mut commit: Commit) {
+        let elapsed = self
+            .params
+            .clock
+            .now()
+            .saturating_duration_since(self.htime);
         info!(
             "Commit {:?} at height {:?}, consensus time {:?}",
-            result.proposal.clone(),
-            self.height,
-            Instant::now() - self.htime
+            commit.proposal, self.height, elapsed
+        );
+
+        commit.elapsed = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+
+        self.checker
+            .record_commit(self.height, commit.round, &commit.proposal);
+        self.last_commit_round = Some(commit.round);
+        self.last_commit = Some(commit.clone());
+        self.send_bft_msg(CoreOutput::Commit(commit));
+        self.change_to_step(Step::CommitWait);
+        self.prepare_next_proposal();
+    }
+
+    /// A function to ask for the feed of the next height early, if the node is
+    /// predicted to propose at round 0 of it. The authorities of the next height
+    /// come with its status, so the prediction assumes they are unchanged.
+    fn prepare_next_proposal(&mut self) {
+        let next = match self.height.checked_add(1) {
+            Some(next) => next,
+            None => return,
+        };
+        if self.is_observer() || self.feed.as_ref().is_some_and(|f| f.height >= next) {
+            return;
+        }
+        if self.proposer_at(next, INIT_ROUND).ok().flatten() == Some(&self.params.address) {
+            info!("Prepare the proposal of height {:?}", next);
+            self.send_bft_msg(CoreOutput::PrepareProposal { height: next });
+        }
+    }
+
+    /// A function to add a late precommit of an absent authority to the last
+    /// commit, then send the extended commit as `CommitUpdate`.
+    fn try_update_commit(&mut self, vote: &Vote) {
+        let weight = self.vote_weight_of(vote.height, &vote.voter).unwrap_or(0);
+        let commit = match self.last_commit.as_mut() {
+            Some(commit)
+                if vote.vote_type
+                    == if commit.fast_path {
+                        VoteType::Prevote
+                    } else {
+                        VoteType::Precommit
+                    }
+                    && vote.height == commit.height
+                    && vote.round == commit.round
+                    && vote.voter != self.params.address =>
+            {
+                commit
+            }
+            _ => return,
+        };
+        let index = match commit.absent.iter().position(|a| *a == vote.voter) {
+            Some(index) => index,
+            None => return,
+        };
+
+        commit.absent.remove(index);
+        commit.precommits.push(vote.clone());
+        if vote.proposal == commit.proposal {
+            commit.lock_votes.push(vote.clone());
+            commit.precommit_weight = commit.precommit_weight.saturating_add(weight);
+        }
+        info!(
+            "Receive a late precommit of height {:?}, round {:?}, from {:?}, update the commit",
+            vote.height, vote.round, vote.voter
         );
+        let update = commit.clone();
+        self.send_bft_msg(CoreOutput::CommitUpdate(update));
+    }
+
+    #[inline]
+    fn has_content(&self, proposal: &Target) -> bool {
+        proposal.is_nil()
+            || self.proposal_contents.contains(proposal)
+            || self
+                .feed
+                .as_ref()
+                .is_some_and(|feed| feed.height == self.height && feed.proposal == *proposal)
+    }
+
+    fn request_content(&mut self, round: u64, proposal: &Target) {
+        if self.has_content(proposal) || !self.requested_contents.insert(proposal.clone()) {
+            return;
+        }
+        info!(
+            "Request the content of proposal {:?} at height {:?}, round {:?}",
+            proposal, self.height, round
+        );
+        self.send_bft_msg(CoreOutput::RequestProposal {
+            height: self.height,
+            round,
+            digest: proposal.clone(),
+        });
+    }
+
+    fn save_content(&mut self, height: u64, proposal: Target) {
+        if height != self.height {
+            return;
+        }
+        match self.pending_commit.take() {
+            Some(commit) if commit.proposal == proposal => {
+                info!("Receive the content of the held commit {:?}", proposal);
+                self.send_commit(commit);
+            }
+            pending_commit => self.pending_commit = pending_commit,
+        }
+        self.proposal_contents.insert(proposal);
+    }
+
+    /// A function to commit at once if the precommits of the round reach +2/3
+    /// on a proposal, whatever the step and the round of the node are. The
+    /// precommits are the certificate of the commit, the lock is not changed.
+    fn try_commit_round(&mut self, round: u64) -> Result<bool, BftError> {
+        let precommit_set = match self
+            .votes
+            .get_voteset(self.height, round, VoteType::Precommit)
+        {
+            Some(precommit_set) => precommit_set,
+            None => return Ok(false),
+        };
+
+        let proposal = precommit_set
+            .votes_by_proposal
+            .iter()
+            .find(|(hash, count)| !hash.is_nil() && self.cal_above_threshold(**count))
+            .map(|(hash, _)| hash.clone());
+
+        if let Some(proposal) = proposal {
+            info!(
+                "Receive over 2/3 precommit on {:?} at height {:?}, round {:?}, commit at once",
+                proposal, self.height, round
+            );
+            let votes =
+                precommit_set.extract_polc(self.height, round, VoteType::Precommit, &proposal);
+            self.commit(round, proposal, votes, false);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// A function to commit at once on the fast path, if the prevotes of round 0
+    /// on a proposal reach the fast path threshold. The prevotes are the
+    /// certificate of the commit.
+    fn try_fast_commit(&mut self, round: u64) -> Result<bool, BftError> {
+        if round != INIT_ROUND || self.params.fast_commit.is_none() {
+            return Ok(false);
+        }
+        let prevote_set = match self
+            .votes
+            .get_voteset(self.height, round, VoteType::Prevote)
+        {
+            Some(prevote_set) => prevote_set,
+            None => return Ok(false),
+        };
+
+        let proposal = prevote_set
+            .votes_by_proposal
+            .iter()
+            .find(|(hash, count)| !hash.is_nil() && self.cal_fast_threshold(**count))
+            .map(|(hash, _)| hash.clone());
+
+        if let Some(proposal) = proposal {
+            info!(
+                "Receive fast path prevotes on {:?} at height {:?}, commit at once",
+                proposal, self.height
+            );
+            let votes = prevote_set.extract_polc(self.height, round, VoteType::Prevote, &proposal);
+            self.commit(round, proposal, votes, true);
+            return Ok(true);
+        }
+        Ok(false)
+    }
 
-        self.last_commit_round = Some(self.round);
-        self.last_commit_proposal = Some(result.proposal);
+    /// A function to get the proposal the node prevoted in round 0 of the
+    /// height, if the fast path is on. The fast path may have committed it, so
+    /// the node keeps prevoting it in the later rounds unless a PoLC of a later
+    /// round unlocks it, or the prevotes of round 0 show that the fast path
+    /// failed.
+    fn fast_path_lock(&mut self) -> Option<Target> {
+        let (numerator, denominator) = self.params.fast_commit?;
+        if self.round == INIT_ROUND {
+            return None;
+        }
+        let proposal = self
+            .votes
+            .get_vote(
+                self.height,
+                INIT_ROUND,
+                VoteType::Prevote,
+                &self.params.address,
+            )
+            .or_else(|| {
+                self.safety_state
+                    .get_vote(self.height, INIT_ROUND, &VoteType::Prevote)
+                    .map(|vote| vote.proposal.clone())
+            })
+            .filter(|proposal| !proposal.is_nil())?;
+
+        // the byzantine authorities have less than 1/3 of the weight, so the
+        // proposal can not reach the threshold once the other prevotes exceed
+        // 1 - threshold + 1/3 of the weight
+        let others = self
+            .votes
+            .get_voteset(self.height, INIT_ROUND, VoteType::Prevote)
+            .map(|prevote_set| {
+                prevote_set
+                    .votes_by_proposal
+                    .iter()
+                    .filter(|(hash, _)| **hash != proposal)
+                    .map(|(_, count)| u128::from(*count))
+                    .sum::<u128>()
+            })
+            .unwrap_or(0);
+        let denominator = u128::from(denominator);
+        let numerator = u128::from(numerator).min(denominator);
+        if others * 3 * denominator > self.total_weight() * (4 * denominator - 3 * numerator) {
+            return None;
+        }
+        Some(proposal)
     }
 
-    fn set_polc(&mut self, hash: &Target, voteset: &VoteSet, vote_type: VoteType) {
+    fn set_polc(
+        &mut self,
+        hash: &Target,
+        voteset: &VoteSet,
+        vote_type: VoteType,
+    ) -> Result<(), BftError> {
         self.proposal = Some(hash.to_owned());
+        self.checker.record_lock(self.height, self.round, hash);
         self.lock_status = Some(LockStatus {
             proposal: hash.to_owned(),
             round: self.round,
             votes: voteset.extract_polc(self.height, self.round, vote_type, hash),
         });
+        self.save_lock()?;
+        // fetch the content in advance, a commit may follow the PoLC
+        self.request_content(self.round, hash);
 
         info!(
             "Get PoLC at height {:?}, round {:?}, on proposal {:?}",
@@ -784,28 +1601,132 @@ where
             self.round,
             hash.to_owned()
         );
+        Ok(())
     }
 
-    fn clean_polc(&mut self) {
+    fn clean_polc(&mut self) -> Result<(), BftError> {
         self.proposal = None;
         self.lock_status = None;
+        self.checker.record_unlock(self.height, self.round);
+        self.save_lock()?;
         trace!(
             "Clean PoLC at height {:?}, round {:?}",
             self.height,
             self.round
         );
+        Ok(())
+    }
+
+    /// A function to ask for the next round. Return true if the local round
+    /// change completes +2/3 and the node goes to the next round.
+    fn transmit_round_change(&mut self) -> Result<bool, BftError> {
+        let round = self.next_round()?;
+        let round_change = RoundChange {
+            height: self.height,
+            round,
+            voter: self.params.address.clone(),
+        };
+
+        info!(
+            "Transmit round change at height {:?}, to round {:?}",
+            self.height, round
+        );
+
+        self.send_bft_msg(CoreOutput::RoundChange(round_change));
+        let weight = self
+            .vote_weight_map
+            .get(&self.params.address)
+            .cloned()
+            .unwrap_or(0);
+        Ok(self.add_round_change(round, self.params.address.clone(), weight))
+    }
+
+    fn try_handle_round_change(&mut self, round_change: RoundChange) -> bool {
+        if round_change.height != self.height
+            || round_change.round <= self.round
+            || round_change.voter == self.params.address
+            || self.step >= Step::Commit
+        {
+            return false;
+        }
+        if round_change.round - self.round > FUTURE_ROUND_WINDOW {
+            warn!(
+                "Drop the round change to round {:?} from {:?}, self round is {:?}",
+                round_change.round, round_change.voter, self.round
+            );
+            return false;
+        }
+
+        let weight = if let Some(weight) = self.vote_weight_map.get(&round_change.voter) {
+            *weight
+        } else {
+            error!("Lose vote weight of node {:?}", round_change.voter);
+            return false;
+        };
+
+        info!(
+            "Receive a round change of height {:?}, to round {:?}, from {:?}",
+            round_change.height, round_change.round, round_change.voter
+        );
+
+        self.add_round_change(round_change.round, round_change.voter, weight)
+    }
+
+    /// A function to check whether the round change is sent by the proposer of
+    /// the current round to the next round while the node waits for the
+    /// proposal, which means the proposer skips proposing.
+    fn is_skipped_by(&self, round_change: &RoundChange) -> Result<bool, BftError> {
+        if self.step != Step::ProposeWait
+            || self.is_observer()
+            || round_change.height != self.height
+            || Some(round_change.round) != self.round.checked_add(1)
+        {
+            return Ok(false);
+        }
+        Ok(self.proposer_of(self.round)? == Some(&round_change.voter))
     }
 
-    fn try_handle_status(&mut self, rich_status: Status) -> bool {
+    /// A function to add a round change of the local node or a received one.
+    /// Return true if the round changes reach +2/3 and the node goes to the
+    /// round.
+    fn add_round_change(&mut self, round: u64, voter: Address, weight: u64) -> bool {
+        if let Some(sum) = self.round_changes.add(round, voter, weight) {
+            if self.cal_above_threshold(sum) {
+                // receive +2/3 round change, goto the round directly
+                info!("Receive over 2/3 round change, goto round {:?}", round);
+                if self.lock_status.is_none() {
+                    self.proposal = None;
+                }
+                self.goto_round(round);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn try_handle_status(&mut self, rich_status: Status) -> Result<bool, BftError> {
         // receive a rich status that height ge self.height is the only way to go to new height
         if rich_status.height >= self.height {
+            let new_height = rich_status
+                .height
+                .checked_add(1)
+                .ok_or(BftError::Overflow)?;
+            let was_observer = self.is_observer();
             if rich_status.height > self.height {
                 // recvive higher status, clean last commit info then go to new height
-                self.last_commit_proposal = None;
                 self.last_commit_round = None;
+                self.last_commit = None;
             }
+            // keep the authorities of the left height for its late messages
+            let window = self.params.authority_window;
+            if window > 0 && !self.vote_weight_map.is_empty() {
+                self.past_vote_weights
+                    .insert(self.height, self.vote_weight_map.clone());
+            }
+            self.past_vote_weights
+                .retain(|height, _| height.saturating_add(window) >= new_height);
             // goto new height directly and update authorty list
-            self.goto_new_height(rich_status.height + 1);
+            self.goto_new_height(new_height);
             self.authority_list = rich_status.get_address_list();
             self.propose_weight = rich_status.get_propose_weight_list();
             self.vote_weight_map = rich_status.get_vote_weight_map();
@@ -815,13 +1736,17 @@ where
                 self.params.timer.set_total_duration(interval);
             }
 
-            info!(
-                "Receive rich status, goto new height {:?}",
-                rich_status.height + 1
-            );
-            return true;
+            info!("Receive rich status, goto new height {:?}", new_height);
+            if self.is_observer() != was_observer {
+                if self.is_observer() {
+                    info!("Become an observer at height {:?}", new_height);
+                } else {
+                    info!("Become a validator at height {:?}", new_height);
+                }
+            }
+            return Ok(true);
         }
-        false
+        Ok(false)
     }
 
     fn try_handle_feed(&mut self, feed: Feed) -> bool {
@@ -837,8 +1762,8 @@ where
 
     #[cfg(feature = "async_verify")]
     fn save_verify_resp(&mut self, verify_result: VerifyResp) {
-        if self.verify_result.contains_key(&verify_result.proposal) {
-            if &verify_result.is_pass != self.verify_result.get(&verify_result.proposal).unwrap() {
+        if let Some(is_pass) = self.verify_result.get(&verify_result.proposal) {
+            if verify_result.is_pass != *is_pass {
                 error!(
                     "The verify results of {:?} are different!",
                     verify_result.proposal
@@ -855,91 +1780,186 @@ where
             .or_insert(verify_result.is_pass);
     }
 
-    fn new_round_start(&mut self) {
+    /// A function to move an observer on once the precommits of the round
+    /// reach +2/3 without a commit, as the validators go to the next round then.
+    /// The saved proposal of the new round is accepted.
+    fn follow_round(&mut self, round: u64) -> Result<(), BftError> {
+        let count = self
+            .votes
+            .get_voteset(self.height, round, VoteType::Precommit)
+            .map(|set| set.count);
+        if round < self.round || !count.is_some_and(|count| self.cal_above_threshold(count)) {
+            return Ok(());
+        }
+        let next = round.checked_add(1).ok_or(BftError::Overflow)?;
+        info!("Observer follows the validators to round {:?}", next);
+        self.goto_round(next);
+        self.new_round_start()
+    }
+
+    fn new_round_start(&mut self) -> Result<(), BftError> {
         if self.step != Step::ProposeWait {
             info!("Start height {:?}, round{:?}", self.height, self.round);
         }
-        if self.is_proposer() {
+        if self.is_observer() {
+            // an observer waits for the proposals and the votes only
+            self.change_to_step(Step::ProposeWait);
+            return self.take_future_proposal();
+        }
+        if self.is_proposer()? {
             if self.try_transmit_proposal() {
-                self.transmit_prevote();
+                self.transmit_prevote()?;
                 self.change_to_step(Step::Prevote);
-                if self.check_prevote_count() {
+                if self.try_fast_commit(self.round)? {
+                    return Ok(());
+                }
+                if self.check_prevote_count()? {
                     self.change_to_step(Step::PrevoteWait);
                 }
             } else {
                 self.send_bft_msg(CoreOutput::GetProposalRequest(self.height));
                 self.change_to_step(Step::ProposeWait);
+                if self.params.empty_block == EmptyBlockPolicy::Skip {
+                    info!(
+                        "Skip proposing at height {:?}, round {:?}",
+                        self.height, self.round
+                    );
+                    self.skip_propose()?;
+                }
             }
         } else {
             self.change_to_step(Step::ProposeWait);
+            self.take_future_proposal()?;
         }
+        Ok(())
     }
 
-    fn process(&mut self, bft_msg: CoreInput) {
+    /// A function to give up the proposal of the round, it asks for the next
+    /// round and prevotes without the proposal.
+    fn skip_propose(&mut self) -> Result<(), BftError> {
+        if self.transmit_round_change()? {
+            return self.new_round_start();
+        }
+        self.change_to_step(Step::Prevote);
+        self.transmit_prevote()?;
+        if self.try_fast_commit(self.round)? {
+            return Ok(());
+        }
+        if self.check_prevote_count()? {
+            self.change_to_step(Step::PrevoteWait);
+        }
+        Ok(())
+    }
+
+    /// A function to process a message. Any message from the network can not
+    /// panic the state machine, an invalid one leads to an error.
+    pub(crate) fn process(&mut self, bft_msg: CoreInput) -> Result<(), BftError> {
         match bft_msg {
             CoreInput::Proposal(proposal) => {
-                if self.step <= Step::ProposeWait {
-                    if let Some(prop) = self.handle_proposal(proposal) {
-                        self.set_proposal(prop);
-                        if self.step == Step::ProposeWait {
-                            self.change_to_step(Step::Prevote);
-                            self.transmit_prevote();
-                            if self.check_prevote_count() {
-                                self.change_to_step(Step::PrevoteWait);
-                            }
+                if let Some(prop) = self.handle_proposal(proposal)? {
+                    // the content of a checked proposal is in the application
+                    self.save_content(prop.height, prop.content.clone());
+                    if self.step <= Step::ProposeWait {
+                        if prop.round > self.round && prop.lock_round.is_none() {
+                            self.save_future_proposal(prop);
+                        } else {
+                            self.accept_proposal(prop)?;
                         }
+                    } else if prop.round > self.round {
+                        // the node is still voting in an earlier round
+                        self.save_future_proposal(prop);
                     }
                 }
             }
             CoreInput::Vote(vote) => {
-                if vote.vote_type == VoteType::Prevote {
+                if vote.vote_type == VoteType::Generic {
+                    // a vote of the chained mode
+                    warn!("Receive a generic vote from {:?}", vote.voter);
+                    return Ok(());
+                }
+                self.try_update_commit(&vote);
+                if vote.height < self.height {
+                    // a vote of the last height only helps the lagging node,
+                    // whatever the step is
+                    let _ = self.try_save_vote(vote)?;
+                } else if vote.vote_type == VoteType::Prevote {
+                    let round = vote.round;
                     if self.step <= Step::PrevoteWait {
-                        let _ = self.try_save_vote(vote);
-                        if self.step >= Step::Prevote && self.check_prevote_count() {
+                        let saved = self.try_save_vote(vote)?;
+                        if saved && self.try_fast_commit(round)? {
+                            return Ok(());
+                        }
+                        if self.step >= Step::Prevote && self.check_prevote_count()? {
                             self.change_to_step(Step::PrevoteWait);
                         }
+                    } else if self.step < Step::Commit
+                        && round == INIT_ROUND
+                        && self.params.fast_commit.is_some()
+                    {
+                        // the prevotes of round 0 may still reach the fast path
+                        if self.try_save_vote(vote)? {
+                            let _ = self.try_fast_commit(round)?;
+                        }
                     }
                 } else if vote.vote_type == VoteType::Precommit {
-                    if self.step < Step::Precommit {
-                        let _ = self.try_save_vote(vote.clone());
-                    }
-                    if (self.step == Step::Precommit || self.step == Step::PrecommitWait)
-                        && self.try_save_vote(vote)
+                    let round = vote.round;
+                    if self.step < Step::Commit
+                        && self.try_save_vote(vote)?
+                        && !self.try_commit_round(round)?
                     {
-                        let precommit_result = self.check_precommit_count();
+                        if self.step == Step::Precommit || self.step == Step::PrecommitWait {
+                            let precommit_result = self.check_precommit_count()?;
 
-                        if precommit_result == PRECOMMIT_ON_NOTHING {
-                            // only receive +2/3 precommits might lead BFT to PrecommitWait
-                            self.change_to_step(Step::PrecommitWait);
-                        }
+                            if precommit_result == PRECOMMIT_ON_NOTHING {
+                                // only receive +2/3 precommits might lead BFT to PrecommitWait
+                                self.change_to_step(Step::PrecommitWait);
+                            }
 
-                        if precommit_result == PRECOMMIT_ON_NIL {
-                            // receive +2/3 on nil, goto next round directly
-                            if self.lock_status.is_none() {
-                                self.proposal = None;
+                            if precommit_result == PRECOMMIT_ON_NIL {
+                                // receive +2/3 on nil, goto next round directly
+                                if self.lock_status.is_none() {
+                                    self.proposal = None;
+                                }
+                                self.goto_next_round()?;
+                                self.new_round_start()?;
                             }
-                            self.goto_next_round();
-                            self.new_round_start();
-                        }
-                        if precommit_result == PRECOMMIT_ON_PROPOSAL {
-                            // receive +2/3 on a proposal, try to commit
-                            self.change_to_step(Step::Commit);
-                            self.proc_commit();
-                            self.change_to_step(Step::CommitWait);
+                            if precommit_result == PRECOMMIT_ON_PROPOSAL {
+                                // receive +2/3 on a proposal, try to commit
+                                self.proc_commit()?;
+                            }
+                        } else if self.is_observer() {
+                            self.follow_round(round)?;
                         }
                     }
                 } else {
                     error!("Invalid Vote Type!");
                 }
             }
+            CoreInput::RoundChange(round_change) => {
+                let skipped = self.is_skipped_by(&round_change)?;
+                if self.try_handle_round_change(round_change) {
+                    self.new_round_start()?;
+                } else if skipped {
+                    // the proposer gives up the round, prevote nil at once
+                    // instead of waiting for the propose timer
+                    info!(
+                        "The proposer skips round {:?} at height {:?}",
+                        self.round, self.height
+                    );
+                    self.skip_propose()?;
+                }
+            }
+            CoreInput::ProposalContent(content) => {
+                self.save_content(content.height, content.digest);
+            }
             CoreInput::Feed(feed) => {
                 if self.try_handle_feed(feed) && self.step == Step::ProposeWait {
-                    self.new_round_start();
+                    self.new_round_start()?;
                 }
             }
             CoreInput::Status(rich_status) => {
-                if self.try_handle_status(rich_status) {
-                    self.new_round_start();
+                if self.try_handle_status(rich_status)? {
+                    self.new_round_start()?;
                 }
             }
 
@@ -949,12 +1969,12 @@ where
                 if self.step == Step::VerifyWait {
                     // next do precommit
                     self.change_to_step(Step::Precommit);
-                    if self.check_verify() == VerifyResult::Undetermined {
+                    if self.check_verify()? == VerifyResult::Undetermined {
                         self.change_to_step(Step::VerifyWait);
-                        return;
+                        return Ok(());
                     }
-                    self.transmit_precommit();
-                    let precommit_result = self.check_precommit_count();
+                    self.transmit_precommit()?;
+                    let precommit_result = self.check_precommit_count()?;
 
                     if precommit_result == PRECOMMIT_ON_NOTHING {
                         // only receive +2/3 precommits might lead BFT to PrecommitWait
@@ -965,42 +1985,38 @@ where
                         if self.lock_status.is_none() {
                             self.proposal = None;
                         }
-                        self.goto_next_round();
-                        self.new_round_start();
+                        self.goto_next_round()?;
+                        self.new_round_start()?;
                     }
                     if precommit_result == PRECOMMIT_ON_PROPOSAL {
-                        self.change_to_step(Step::Commit);
-                        self.proc_commit();
-                        self.change_to_step(Step::CommitWait);
+                        self.proc_commit()?;
                     }
                 }
             }
 
             _ => error!("Invalid Message!"),
         }
+        Ok(())
     }
 
-    fn timeout_process(&mut self, tminfo: &TimeoutInfo) {
+    /// A function to process a timeout.
+    pub(crate) fn timeout_process(&mut self, tminfo: &TimeoutInfo) -> Result<(), BftError> {
         if tminfo.height < self.height {
-            return;
+            return Ok(());
         }
         if tminfo.height == self.height && tminfo.round < self.round {
-            return;
+            return Ok(());
         }
         if tminfo.height == self.height && tminfo.round == self.round && tminfo.step != self.step {
-            return;
+            return Ok(());
         }
 
         match tminfo.step {
             Step::ProposeWait => {
-                self.change_to_step(Step::Prevote);
-                self.transmit_prevote();
-                if self.check_prevote_count() {
-                    self.change_to_step(Step::PrevoteWait);
-                }
+                self.skip_propose()?;
             }
             Step::Prevote => {
-                self.transmit_prevote();
+                self.transmit_prevote()?;
             }
             Step::PrevoteWait => {
                 // if there is no lock, clear the proposal
@@ -1011,15 +2027,15 @@ where
                 self.change_to_step(Step::Precommit);
                 #[cfg(feature = "async_verify")]
                 {
-                    let verify_result = self.check_verify();
+                    let verify_result = self.check_verify()?;
                     if verify_result == VerifyResult::Undetermined {
                         self.change_to_step(Step::VerifyWait);
-                        return;
+                        return Ok(());
                     }
                 }
 
-                self.transmit_precommit();
-                let precommit_result = self.check_precommit_count();
+                self.transmit_precommit()?;
+                let precommit_result = self.check_precommit_count()?;
 
                 if precommit_result == PRECOMMIT_ON_NOTHING {
                     // only receive +2/3 precommits might lead BFT to PrecommitWait
@@ -1030,35 +2046,35 @@ where
                     if self.lock_status.is_none() {
                         self.proposal = None;
                     }
-                    self.goto_next_round();
-                    self.new_round_start();
+                    self.goto_next_round()?;
+                    self.new_round_start()?;
                 }
                 if precommit_result == PRECOMMIT_ON_PROPOSAL {
-                    self.change_to_step(Step::Commit);
-                    self.proc_commit();
-                    self.change_to_step(Step::CommitWait);
+                    self.proc_commit()?;
                 }
             }
             Step::Precommit => {
-                self.transmit_prevote();
-                self.transmit_precommit();
+                self.transmit_prevote()?;
+                self.transmit_precommit()?;
             }
             Step::PrecommitWait => {
                 // receive +2/3 precommits however no proposal reach +2/3
                 // then goto next round directly
-                self.goto_next_round();
-                self.new_round_start();
+                if !self.transmit_round_change()? {
+                    self.goto_next_round()?;
+                }
+                self.new_round_start()?;
             }
 
             #[cfg(feature = "async_verify")]
             Step::VerifyWait => {
                 // clean fsave info
-                self.clean_polc();
+                self.clean_polc()?;
 
                 // next do precommit
                 self.change_to_step(Step::Precommit);
-                self.transmit_precommit();
-                let precommit_result = self.check_precommit_count();
+                self.transmit_precommit()?;
+                let precommit_result = self.check_precommit_count()?;
 
                 if precommit_result == PRECOMMIT_ON_NOTHING {
                     // only receive +2/3 precommits might lead BFT to PrecommitWait
@@ -1069,17 +2085,16 @@ where
                     if self.lock_status.is_none() {
                         self.proposal = None;
                     }
-                    self.goto_next_round();
-                    self.new_round_start();
+                    self.goto_next_round()?;
+                    self.new_round_start()?;
                 }
                 if precommit_result == PRECOMMIT_ON_PROPOSAL {
-                    self.change_to_step(Step::Commit);
-                    self.proc_commit();
-                    self.change_to_step(Step::CommitWait);
+                    self.proc_commit()?;
                 }
             }
 
             _ => error!("Invalid Timeout Info!"),
         }
+        Ok(())
     }
 }
@@ -0,0 +1,1805 @@
+//! A deterministic simulation of a BFT cluster.
+//!
+//! Every honest node or observer is a `Bft` state machine driven by hand, the messages and
+//! timers it sends are collected as pending events instead of being delivered.
+//! A schedule of actions decides which event happens next, so any interleaving
+//! of proposals, votes, timeouts and status messages can be replayed, and
+//! byzantine nodes inject arbitrary votes and proposals. The nodes share a mock
+//! clock, which is advanced to the timeval of each timer delivered.
+
+use super::{Bft, Step, FUTURE_ROUND_WINDOW};
+use crate::{
+    clock::{Clock, MockClock},
+    error::BftError,
+    timer::{TimeoutInfo, TimerCmd, TimerId},
+    types::*,
+    FromCore,
+};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+/// A sink collecting the output of a node, and whether empty blocks are
+/// allowed.
+pub(crate) struct Outbox(Sender<CoreOutput>, bool);
+
+impl FromCore for Outbox {
+    type Error = ();
+
+    fn send_msg(&mut self, msg: CoreOutput) -> Result<(), ()> {
+        self.0.send(msg).map_err(|_| ())
+    }
+
+    fn allow_empty_block(&mut self, _height: u64) -> bool {
+        self.1
+    }
+}
+
+/// A BFT state machine without threads, its output and timers are collected.
+pub(crate) struct SimNode {
+    pub(crate) bft: Bft<Outbox>,
+    outbox: Receiver<CoreOutput>,
+    timers: Receiver<TimerCmd>,
+}
+
+impl SimNode {
+    /// A function to create a new simulated node.
+    pub(crate) fn new(address: Address) -> Self {
+        SimNode::with_clock(address, MockClock::default())
+    }
+
+    /// A function to create a new simulated node on the clock.
+    pub(crate) fn with_clock(address: Address, clock: MockClock) -> Self {
+        let (s, outbox) = unbounded();
+        let (ts, timers) = unbounded();
+        let mut bft = Bft::initialize(Outbox(s, true), ts, address);
+        bft.set_clock(Arc::new(clock));
+        SimNode {
+            bft,
+            outbox,
+            timers,
+        }
+    }
+
+    /// A function to take the messages and timers the node has sent.
+    pub(crate) fn drain(&mut self) -> (Vec<CoreOutput>, Vec<TimerCmd>) {
+        (
+            self.outbox.try_iter().collect(),
+            self.timers.try_iter().collect(),
+        )
+    }
+}
+
+/// The setting of a simulation.
+#[derive(Clone, Debug)]
+pub(crate) struct SimConfig {
+    /// The number of honest nodes.
+    pub(crate) honest: usize,
+    /// The number of byzantine nodes.
+    pub(crate) byzantine: usize,
+    /// The number of observers, which are not in the authority list.
+    pub(crate) observer: usize,
+    /// The simulation finishes once every node commits this height.
+    pub(crate) max_height: u64,
+    /// Events that move a node to this round or beyond are discarded.
+    pub(crate) max_round: u64,
+    /// The maximum number of events to happen.
+    pub(crate) max_steps: usize,
+    /// Whether the vote retransmission timers are kept.
+    pub(crate) retransmit: bool,
+    /// The fast path threshold of the honest nodes.
+    pub(crate) fast_commit: Option<(u64, u64)>,
+}
+
+/// An action of a schedule.
+#[derive(Clone, Debug)]
+pub(crate) enum Action {
+    /// Deliver the pending event of the index, modulo the count of pending events.
+    Deliver(usize),
+    /// Drop the pending message of the index, timers are never dropped.
+    Drop(usize),
+    /// A byzantine node sends the message of the index in its menu.
+    Byzantine(usize),
+}
+
+#[derive(Clone, Debug)]
+enum Event {
+    Msg {
+        to: usize,
+        msg: CoreInput,
+    },
+    Timer {
+        to: usize,
+        id: TimerId,
+        info: TimeoutInfo,
+    },
+}
+
+/// A simulated cluster of honest nodes, byzantine nodes and observers.
+pub(crate) struct Cluster {
+    config: SimConfig,
+    nodes: Vec<SimNode>,
+    authority: Vec<Node>,
+    pending: Vec<Event>,
+    local: VecDeque<(usize, CoreInput)>,
+    /// Commits of each height, the node index and the proposal.
+    commits: BTreeMap<u64, Vec<(usize, Target)>>,
+    /// The proposals that are valid to commit at each height.
+    proposed: BTreeMap<u64, Vec<Target>>,
+    /// The height and the highest lock round of each node.
+    locks: Vec<(u64, Option<u64>)>,
+    steps: usize,
+    clock: MockClock,
+}
+
+impl Cluster {
+    /// A function to create a cluster and start it at height 1.
+    pub(crate) fn new(config: SimConfig) -> Self {
+        let total = config.honest + config.byzantine;
+        let authority: Vec<Node> = (0..total)
+            .map(|i| Node::new(Address::new(vec![i as u8])))
+            .collect();
+        let clock = MockClock::default();
+        let nodes: Vec<SimNode> = (0..config.honest)
+            .map(|i| authority[i].address.clone())
+            .chain((0..config.observer).map(|i| Address::new(vec![(total + i) as u8])))
+            .map(|address| {
+                let mut node = SimNode::with_clock(address, clock.clone());
+                node.bft.params.fast_commit = config.fast_commit;
+                node
+            })
+            .collect();
+
+        let mut cluster = Cluster {
+            locks: vec![(0, None); nodes.len()],
+            config,
+            nodes,
+            authority,
+            pending: Vec::new(),
+            local: VecDeque::new(),
+            commits: BTreeMap::new(),
+            proposed: BTreeMap::new(),
+            steps: 0,
+            clock,
+        };
+
+        for i in 0..cluster.nodes.len() {
+            let status = cluster.status(0);
+            cluster.local.push_back((i, status));
+        }
+        cluster.flush_local().unwrap();
+        cluster
+    }
+
+    /// A function to run the schedule, then deliver the pending events fairly
+    /// until every node commits the last height or the steps run out.
+    pub(crate) fn run(&mut self, actions: &[Action]) -> Result<(), String> {
+        for action in actions {
+            if self.is_finished() {
+                return Ok(());
+            }
+            self.step(action)?;
+        }
+
+        while !self.is_finished() {
+            let index = if let Some(index) = self
+                .pending
+                .iter()
+                .position(|e| matches!(e, Event::Msg { .. }))
+            {
+                index
+            } else if let Some((index, _)) =
+                self.pending
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, e)| match e {
+                        Event::Timer { info, .. } => Some(info.timeval),
+                        _ => None,
+                    })
+            {
+                index
+            } else {
+                return Ok(());
+            };
+            self.deliver(index)?;
+        }
+        Ok(())
+    }
+
+    /// A function to get the count of pending events.
+    pub(crate) fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// A function to get the commits of every height.
+    pub(crate) fn commits(&self) -> &BTreeMap<u64, Vec<(usize, Target)>> {
+        &self.commits
+    }
+
+    /// A function to do an action.
+    pub(crate) fn step(&mut self, action: &Action) -> Result<(), String> {
+        match action {
+            Action::Deliver(index) => {
+                if !self.pending.is_empty() {
+                    let index = index % self.pending.len();
+                    self.deliver(index)?;
+                }
+            }
+            Action::Drop(index) => {
+                if !self.pending.is_empty() {
+                    let index = index % self.pending.len();
+                    if let Event::Msg { .. } = self.pending[index] {
+                        self.pending.remove(index);
+                    }
+                }
+            }
+            Action::Byzantine(index) => self.byzantine(*index)?,
+        }
+        Ok(())
+    }
+
+    fn is_finished(&self) -> bool {
+        self.steps >= self.config.max_steps
+            || self
+                .commits
+                .get(&self.config.max_height)
+                .is_some_and(|c| c.len() == self.nodes.len())
+    }
+
+    fn status(&self, height: u64) -> CoreInput {
+        CoreInput::Status(Status {
+            height,
+            interval: None,
+            authority_list: self.authority.clone(),
+        })
+    }
+
+    fn deliver(&mut self, index: usize) -> Result<(), String> {
+        self.steps += 1;
+        match self.pending.remove(index) {
+            Event::Msg { to, msg } => {
+                #[cfg(feature = "async_verify")]
+                {
+                    if let CoreInput::Proposal(p) = &msg {
+                        self.local.push_back((
+                            to,
+                            CoreInput::VerifyResp(VerifyResp {
+                                is_pass: true,
+                                proposal: p.content.clone(),
+                            }),
+                        ));
+                    }
+                }
+                self.nodes[to].bft.process(msg).map_err(|e| error(to, e))?;
+                self.collect(to)?;
+            }
+            Event::Timer { to, info, .. } => {
+                let now = self.clock.now();
+                self.clock
+                    .advance(info.timeval.saturating_duration_since(now));
+                self.nodes[to]
+                    .bft
+                    .timeout_process(&info)
+                    .map_err(|e| error(to, e))?;
+                self.collect(to)?;
+            }
+        }
+        self.flush_local()
+    }
+
+    fn flush_local(&mut self) -> Result<(), String> {
+        while let Some((to, msg)) = self.local.pop_front() {
+            self.nodes[to].bft.process(msg).map_err(|e| error(to, e))?;
+            self.collect(to)?;
+        }
+        Ok(())
+    }
+
+    /// The byzantine menu is every combination of the receiver, the round, the
+    /// message kind and the target at the height of the receiver.
+    fn byzantine(&mut self, index: usize) -> Result<(), String> {
+        if self.config.byzantine == 0 {
+            return Ok(());
+        }
+        let mut index = index;
+        let mut pick = |n: usize| {
+            let res = index % n;
+            index /= n;
+            res
+        };
+        let sender = self.authority[self.config.honest + pick(self.config.byzantine)]
+            .address
+            .clone();
+        let to = pick(self.nodes.len());
+        let round = pick(self.config.max_round as usize) as u64;
+        let kind = pick(4);
+        let height = self.nodes[to].bft.height;
+        let target = match pick(3) {
+            0 if kind < 2 => Target::new(vec![]),
+            1 => Target::new(vec![0xb0, height as u8]),
+            _ => Target::new(vec![0xb1, height as u8]),
+        };
+
+        let msg = if kind >= 2 {
+            self.proposed
+                .entry(height)
+                .or_default()
+                .push(target.clone());
+            // a fake PoLC of the byzantine prevotes only
+            let lock_round = round.checked_sub(1).filter(|_| kind == 3);
+            let lock_votes = match lock_round {
+                Some(lock_round) => self.authority[self.config.honest..]
+                    .iter()
+                    .map(|node| Vote {
+                        vote_type: VoteType::Prevote,
+                        height,
+                        round: lock_round,
+                        proposal: target.clone(),
+                        voter: node.address.clone(),
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            CoreInput::Proposal(Proposal {
+                height,
+                round,
+                content: target,
+                lock_round,
+                lock_votes,
+                proposer: sender,
+                parent_qc: None,
+            })
+        } else {
+            CoreInput::Vote(Vote {
+                vote_type: if kind == 0 {
+                    VoteType::Prevote
+                } else {
+                    VoteType::Precommit
+                },
+                height,
+                round,
+                proposal: target,
+                voter: sender,
+            })
+        };
+
+        self.steps += 1;
+        self.nodes[to].bft.process(msg).map_err(|e| error(to, e))?;
+        self.collect(to)?;
+        self.flush_local()
+    }
+
+    fn collect(&mut self, from: usize) -> Result<(), String> {
+        let (msgs, timers) = self.nodes[from].drain();
+        for msg in msgs.into_iter() {
+            match msg {
+                CoreOutput::Proposal(p) => self.broadcast(from, CoreInput::Proposal(p)),
+                CoreOutput::Vote(v) => self.broadcast(from, CoreInput::Vote(v)),
+                CoreOutput::RoundChange(rc) => {
+                    if rc.round < self.config.max_round {
+                        self.broadcast(from, CoreInput::RoundChange(rc));
+                    }
+                }
+                CoreOutput::SendTo { to, msg } => {
+                    if let Some(to) = self.node_index(&to) {
+                        self.pending.push(Event::Msg {
+                            to,
+                            msg: msg.into(),
+                        });
+                    }
+                }
+                CoreOutput::Commit(c) => self.check_commit(from, c)?,
+                CoreOutput::Evidence(e) => {
+                    // an honest node never proposes an invalid PoLC
+                    if let Some(culprit) = self.node_index(&e.proposal.proposer) {
+                        return Err(format!(
+                            "Node {:?} reports honest node {:?} for {:?}",
+                            from, culprit, e.reason
+                        ));
+                    }
+                }
+                CoreOutput::CommitUpdate(c) => self.check_commit_update(from, c)?,
+                CoreOutput::RequestProposal { height, digest, .. } => {
+                    // only the proposed contents can be fetched
+                    if self
+                        .proposed
+                        .get(&height)
+                        .is_some_and(|proposed| proposed.contains(&digest))
+                    {
+                        self.pending.push(Event::Msg {
+                            to: from,
+                            msg: CoreInput::ProposalContent(ProposalContent { height, digest }),
+                        });
+                    }
+                }
+                CoreOutput::GetProposalRequest(height) | CoreOutput::PrepareProposal { height } => {
+                    let proposal = Target::new(vec![from as u8 + 1, height as u8]);
+                    self.proposed
+                        .entry(height)
+                        .or_default()
+                        .push(proposal.clone());
+                    self.local
+                        .push_back((from, CoreInput::Feed(Feed { height, proposal })));
+                    #[cfg(feature = "async_verify")]
+                    self.local.push_back((
+                        from,
+                        CoreInput::VerifyResp(VerifyResp {
+                            is_pass: true,
+                            proposal: Target::new(vec![from as u8 + 1, height as u8]),
+                        }),
+                    ));
+                }
+            }
+        }
+
+        for cmd in timers.into_iter() {
+            let (id, info) = match cmd {
+                TimerCmd::Set(id, info) => (id, info),
+                cmd => {
+                    self.pending.retain(|e| match e {
+                        Event::Timer { to, id, info } => *to != from || !cmd.cancels(id, info),
+                        _ => true,
+                    });
+                    continue;
+                }
+            };
+            let is_retransmit = info.step == Step::Prevote || info.step == Step::Precommit;
+            let is_next_round =
+                info.step == Step::PrecommitWait && info.round + 1 >= self.config.max_round;
+            if info.round >= self.config.max_round
+                || is_next_round
+                || (is_retransmit && !self.config.retransmit)
+            {
+                continue;
+            }
+            self.pending.push(Event::Timer { to: from, id, info });
+        }
+
+        self.check_lock(from)
+    }
+
+    fn node_index(&self, address: &Address) -> Option<usize> {
+        let total = self.config.honest + self.config.byzantine;
+        match address.clone().into_vec()[0] as usize {
+            index if index < self.config.honest => Some(index),
+            index if index >= total && index < total + self.config.observer => {
+                Some(self.config.honest + index - total)
+            }
+            _ => None,
+        }
+    }
+
+    fn broadcast(&mut self, from: usize, msg: CoreInput) {
+        for to in (0..self.nodes.len()).filter(|i| *i != from) {
+            self.pending.push(Event::Msg {
+                to,
+                msg: msg.clone(),
+            });
+        }
+    }
+
+    /// Agreement and validity: every commit of a height is the same proposal,
+    /// and it is a proposal of the height.
+    fn check_commit(&mut self, from: usize, commit: Commit) -> Result<(), String> {
+        let valid = self
+            .proposed
+            .get(&commit.height)
+            .is_some_and(|p| p.contains(&commit.proposal));
+        if commit.proposal.is_nil() || !valid {
+            return Err(format!(
+                "Validity violated, node {:?} commits {:?} at height {:?}",
+                from, commit.proposal, commit.height
+            ));
+        }
+
+        let commits = self.commits.entry(commit.height).or_default();
+        if let Some((other, proposal)) = commits.iter().find(|(_, p)| *p != commit.proposal) {
+            return Err(format!(
+                "Agreement violated at height {:?}, node {:?} commits {:?}, node {:?} commits {:?}",
+                commit.height, from, commit.proposal, other, proposal
+            ));
+        }
+        commits.push((from, commit.proposal));
+
+        // go on to the height after the last one, so that the node helps the
+        // others to commit the last height
+        if commit.height <= self.config.max_height {
+            let status = self.status(commit.height);
+            self.local.push_back((from, status));
+        }
+        Ok(())
+    }
+
+    /// An update only extends the commit of the node with the precommits on the
+    /// proposal of absent authorities.
+    fn check_commit_update(&self, from: usize, update: Commit) -> Result<(), String> {
+        let committed = self
+            .commits
+            .get(&update.height)
+            .is_some_and(|commits| commits.contains(&(from, update.proposal.clone())));
+        let voters: Vec<&Address> = update.precommits.iter().map(|v| &v.voter).collect();
+        let valid = update
+            .lock_votes
+            .iter()
+            .all(|v| v.proposal == update.proposal)
+            && update.absent.iter().all(|a| !voters.contains(&a))
+            && (1..voters.len()).all(|i| !voters[..i].contains(&voters[i]));
+        if !committed || !valid {
+            return Err(format!(
+                "Invalid commit update of node {:?} at height {:?}: {:?}",
+                from, update.height, update
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lock monotonicity: the lock round of a node never decreases in a height.
+    fn check_lock(&mut self, index: usize) -> Result<(), String> {
+        let bft = &self.nodes[index].bft;
+        let lock_round = bft.lock_status.as_ref().map(|l| l.round);
+        let (height, highest) = self.locks[index];
+        if height != bft.height {
+            self.locks[index] = (bft.height, lock_round);
+            return Ok(());
+        }
+
+        match (highest, lock_round) {
+            (Some(highest), Some(round)) if round < highest => Err(format!(
+                "Lock monotonicity violated, node {:?} locks at round {:?} after round {:?}",
+                index, round, highest
+            )),
+            (_, Some(_)) => {
+                self.locks[index] = (height, lock_round);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn error(index: usize, e: BftError) -> String {
+    format!("Node {:?} fails to process, {:?}", index, e)
+}
+
+/// A function to explore every schedule of at most `depth` deliveries with
+/// depth-first search, each schedule is replayed from the start and completed
+/// by fair delivery. Return the count of explored schedules.
+pub(crate) fn model_check(config: &SimConfig, depth: usize) -> Result<usize, String> {
+    let mut count = 0;
+    let mut stack = vec![Vec::new()];
+    while let Some(prefix) = stack.pop() {
+        let mut cluster = Cluster::new(config.clone());
+        for index in prefix.iter() {
+            cluster.step(&Action::Deliver(*index))?;
+        }
+        let branch = cluster.pending_count();
+        if prefix.len() < depth && branch > 0 {
+            for index in (0..branch).rev() {
+                let mut next = prefix.clone();
+                next.push(index);
+                stack.push(next);
+            }
+            continue;
+        }
+
+        count += 1;
+        cluster
+            .run(&[])
+            .map_err(|e| format!("{} with schedule {:?}", e, prefix))?;
+    }
+    Ok(count)
+}
+
+mod test {
+    use super::*;
+    use crate::{
+        config::{BftConfig, EmptyBlockPolicy},
+        safety::FileSafetyStore,
+    };
+    use proptest::prelude::*;
+    use std::time::Duration;
+
+    fn action() -> impl Strategy<Value = Action> {
+        prop_oneof![
+            8 => any::<usize>().prop_map(Action::Deliver),
+            1 => any::<usize>().prop_map(Action::Drop),
+            2 => any::<usize>().prop_map(Action::Byzantine),
+        ]
+    }
+
+    fn config(honest: usize, byzantine: usize) -> SimConfig {
+        SimConfig {
+            honest,
+            byzantine,
+            observer: 1,
+            max_height: 2,
+            max_round: 4,
+            max_steps: 3000,
+            retransmit: true,
+            fast_commit: None,
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_honest_cluster(actions in prop::collection::vec(action(), 0..300)) {
+            let mut cluster = Cluster::new(config(4, 0));
+            prop_assert_eq!(cluster.run(&actions), Ok(()));
+        }
+
+        #[test]
+        fn prop_byzantine_cluster(actions in prop::collection::vec(action(), 0..300)) {
+            let mut cluster = Cluster::new(config(3, 1));
+            prop_assert_eq!(cluster.run(&actions), Ok(()));
+        }
+
+        #[test]
+        fn prop_fast_commit_cluster(actions in prop::collection::vec(action(), 0..300)) {
+            let mut config = config(3, 1);
+            config.fast_commit = Some((1, 1));
+            let mut cluster = Cluster::new(config);
+            prop_assert_eq!(cluster.run(&actions), Ok(()));
+        }
+    }
+
+    fn gen_status(height: u64, weights: &[(u8, u64)]) -> CoreInput {
+        let authority_list = weights
+            .iter()
+            .map(|(addr, weight)| {
+                let mut node = Node::new(Address::new(vec![*addr]));
+                node.set_propose_weight(*weight);
+                node.set_vote_weight(*weight);
+                node
+            })
+            .collect();
+        CoreInput::Status(Status {
+            height,
+            interval: None,
+            authority_list,
+        })
+    }
+
+    /// A function to get the proposers of the first rounds at height 1.
+    fn proposers(authority: &[(u8, u64)], rounds: u64) -> Vec<Address> {
+        let mut probe = SimNode::new(Address::new(vec![0]));
+        assert_eq!(probe.bft.process(gen_status(0, authority)), Ok(()));
+        (0..rounds)
+            .map(|round| probe.bft.proposer_of(round).unwrap().unwrap().clone())
+            .collect()
+    }
+
+    /// A function to start the node at height 1 with the config, which runs on
+    /// the clock of the node. Return the messages and the timers of the start.
+    fn start(
+        node: &mut SimNode,
+        authority: &[(u8, u64)],
+        mut config: BftConfig,
+    ) -> (Vec<CoreOutput>, Vec<TimerCmd>) {
+        config.set_clock(Arc::clone(&node.bft.params.clock));
+        node.bft.apply_config(config);
+        assert_eq!(node.bft.process(gen_status(0, authority)), Ok(()));
+        node.drain()
+    }
+
+    /// A function to get the rounds and the proposals of the votes of the type.
+    fn votes(msgs: Vec<CoreOutput>, vote_type: VoteType) -> Vec<(u64, Target)> {
+        msgs.into_iter()
+            .filter_map(|msg| match msg {
+                CoreOutput::Vote(v) if v.vote_type == vote_type => Some((v.round, v.proposal)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_inputs() {
+        let mut node = SimNode::new(Address::new(vec![0]));
+        let mut vote = Vote {
+            vote_type: VoteType::Prevote,
+            height: 0,
+            round: 0,
+            proposal: Target::new(vec![1]),
+            voter: Address::new(vec![0]),
+        };
+        assert_eq!(node.bft.process(CoreInput::Vote(vote.clone())), Ok(()));
+
+        // a forged vote of the local node is not saved as its own vote
+        assert_eq!(node.bft.process(gen_status(0, &[(0, 1), (1, 1)])), Ok(()));
+        vote.height = 1;
+        vote.vote_type = VoteType::Precommit;
+        assert_eq!(node.bft.process(CoreInput::Vote(vote.clone())), Ok(()));
+        assert_eq!(
+            node.bft
+                .votes
+                .get_vote(1, 0, VoteType::Precommit, &vote.voter),
+            None
+        );
+
+        assert_eq!(
+            node.bft.process(gen_status(u64::MAX, &[(0, 1)])),
+            Err(BftError::Overflow)
+        );
+        assert_eq!(
+            node.bft
+                .process(gen_status(1, &[(0, u64::MAX), (1, u64::MAX)])),
+            Err(BftError::InvalidAuthority)
+        );
+    }
+
+    #[test]
+    fn test_observer_mode() {
+        let mut node = SimNode::new(Address::new(vec![0]));
+        let precommit = |voter: u8| {
+            CoreInput::Vote(Vote {
+                vote_type: VoteType::Precommit,
+                height: 1,
+                round: 2,
+                proposal: Target::new(vec![1]),
+                voter: Address::new(vec![voter]),
+            })
+        };
+
+        // the local node is not in the authority list, it never votes
+        assert_eq!(node.bft.process(gen_status(0, &[(1, 1), (2, 1)])), Ok(()));
+        let proposal = CoreInput::Proposal(Proposal {
+            height: 1,
+            round: 2,
+            content: Target::new(vec![1]),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: node.bft.proposer_of(2).unwrap().unwrap().clone(),
+            parent_qc: None,
+        });
+        assert_eq!(node.bft.process(proposal), Ok(()));
+        assert_eq!(node.bft.process(precommit(1)), Ok(()));
+        assert_eq!(node.drain().0, vec![]);
+
+        // commit once the precommits reach +2/3
+        assert_eq!(node.bft.process(precommit(2)), Ok(()));
+        let (msgs, timers) = node.drain();
+        assert!(!timers.iter().any(|cmd| matches!(cmd, TimerCmd::Set(..))));
+        match &msgs[..] {
+            [CoreOutput::Commit(commit)] => {
+                assert_eq!((commit.height, commit.round), (1, 2));
+                assert_eq!(commit.proposal, Target::new(vec![1]));
+                assert_eq!(commit.lock_votes.len(), 2);
+            }
+            _ => panic!("Unexpected output {:?}", msgs),
+        }
+
+        // become a validator with the vote weight
+        assert_eq!(node.bft.process(gen_status(1, &[(0, 1), (1, 0)])), Ok(()));
+        let (msgs, _) = node.drain();
+        assert!(msgs
+            .iter()
+            .any(|msg| matches!(msg, CoreOutput::GetProposalRequest(2))));
+    }
+
+    #[test]
+    fn test_observer_follows_round() {
+        let mut node = SimNode::new(Address::new(vec![0]));
+        assert_eq!(
+            node.bft.process(gen_status(0, &[(1, 1), (2, 1), (3, 1)])),
+            Ok(())
+        );
+
+        // the proposal of round 1 is saved until the observer enters round 1
+        let proposal = CoreInput::Proposal(Proposal {
+            height: 1,
+            round: 1,
+            content: Target::new(vec![5]),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: node.bft.proposer_of(1).unwrap().unwrap().clone(),
+            parent_qc: None,
+        });
+        assert_eq!(node.bft.process(proposal), Ok(()));
+        assert_eq!(node.bft.round, 0);
+
+        // the validators precommit to nil in round 0 and go to round 1
+        for voter in 1..4 {
+            let precommit = CoreInput::Vote(Vote {
+                vote_type: VoteType::Precommit,
+                height: 1,
+                round: 0,
+                proposal: Target::new(vec![]),
+                voter: Address::new(vec![voter]),
+            });
+            assert_eq!(node.bft.process(precommit), Ok(()));
+        }
+        assert_eq!(node.bft.round, 1);
+        assert_eq!(node.bft.proposal, Some(Target::new(vec![5])));
+        assert!(node.bft.future_proposals.is_empty());
+        assert!(!node
+            .drain()
+            .0
+            .iter()
+            .any(|msg| matches!(msg, CoreOutput::Vote(_))));
+    }
+
+    #[test]
+    fn test_local_round_change_quorum() {
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let mut probe = SimNode::new(Address::new(vec![0]));
+        assert_eq!(probe.bft.process(gen_status(0, &authority)), Ok(()));
+        let proposer = probe.bft.proposer_of(0).unwrap().unwrap().clone();
+        let mut others = (0..4)
+            .map(|i| Address::new(vec![i]))
+            .filter(|a| *a != proposer);
+        let address = others.next().unwrap();
+
+        let mut node = SimNode::new(address);
+        assert_eq!(node.bft.process(gen_status(0, &authority)), Ok(()));
+        assert_eq!(node.bft.step, Step::ProposeWait);
+        for voter in others {
+            let round_change = RoundChange {
+                height: 1,
+                round: 1,
+                voter,
+            };
+            assert_eq!(
+                node.bft.process(CoreInput::RoundChange(round_change)),
+                Ok(())
+            );
+        }
+        assert_eq!(node.bft.round, 0);
+
+        // the round change of the local node completes +2/3 on the propose
+        // timeout, the node goes to round 1 without a prevote of round 0
+        let (_, timers) = node.drain();
+        for cmd in timers {
+            if let TimerCmd::Set(_, info) = cmd {
+                if info.step == Step::ProposeWait {
+                    assert_eq!(node.bft.timeout_process(&info), Ok(()));
+                }
+            }
+        }
+        assert_eq!(node.bft.round, 1);
+        assert!(!node
+            .drain()
+            .0
+            .iter()
+            .any(|msg| matches!(msg, CoreOutput::Vote(v) if v.round == 0)));
+    }
+
+    #[test]
+    fn test_round_change_window() {
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let mut node = SimNode::new(Address::new(vec![0]));
+        start(&mut node, &authority, BftConfig::new());
+        let voter = Address::new(vec![1]);
+
+        // the round changes of the rounds out of the window are dropped
+        for round in (1..FUTURE_ROUND_WINDOW * 4).chain(vec![u64::MAX]) {
+            let round_change = RoundChange {
+                height: 1,
+                round,
+                voter: voter.clone(),
+            };
+            assert_eq!(
+                node.bft.process(CoreInput::RoundChange(round_change)),
+                Ok(())
+            );
+        }
+        assert_eq!(node.bft.round, 0);
+        assert_eq!(
+            node.bft.round_changes.senders.len() as u64,
+            FUTURE_ROUND_WINDOW
+        );
+        assert_eq!(
+            node.bft.round_changes.weight.keys().max(),
+            Some(&FUTURE_ROUND_WINDOW)
+        );
+    }
+
+    #[test]
+    fn test_commit_in_any_round() {
+        let precommit = |round: u64, voter: u8| {
+            CoreInput::Vote(Vote {
+                vote_type: VoteType::Precommit,
+                height: 1,
+                round,
+                proposal: Target::new(vec![7]),
+                voter: Address::new(vec![voter]),
+            })
+        };
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let content = CoreInput::ProposalContent(ProposalContent {
+            height: 1,
+            digest: Target::new(vec![7]),
+        });
+
+        // the precommits of a later round
+        let mut node = SimNode::new(Address::new(vec![0]));
+        assert_eq!(node.bft.process(gen_status(0, &authority)), Ok(()));
+        assert_eq!(node.bft.process(content.clone()), Ok(()));
+        for voter in 1..4 {
+            assert_eq!(node.bft.process(precommit(1, voter)), Ok(()));
+        }
+        assert_eq!(node.bft.step, Step::CommitWait);
+        assert!(node.bft.lock_status.is_none());
+
+        // the precommits of an earlier round
+        let mut node = SimNode::new(Address::new(vec![0]));
+        assert_eq!(node.bft.process(gen_status(0, &authority)), Ok(()));
+        assert_eq!(node.bft.process(content), Ok(()));
+        for voter in 1..4 {
+            let round_change = CoreInput::RoundChange(RoundChange {
+                height: 1,
+                round: 2,
+                voter: Address::new(vec![voter]),
+            });
+            assert_eq!(node.bft.process(round_change), Ok(()));
+        }
+        assert_eq!(node.bft.round, 2);
+        for voter in 1..4 {
+            assert_eq!(node.bft.process(precommit(0, voter)), Ok(()));
+        }
+        assert_eq!(node.bft.step, Step::CommitWait);
+
+        let (msgs, _) = node.drain();
+        let commit = msgs
+            .into_iter()
+            .find_map(|msg| match msg {
+                CoreOutput::Commit(commit) => Some(commit),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((commit.round, commit.lock_votes.len()), (0, 3));
+        assert_eq!(commit.precommits.len(), 3);
+        assert_eq!(commit.absent, vec![Address::new(vec![0])]);
+        assert_eq!((commit.precommit_weight, commit.total_weight), (3, 4));
+        assert_eq!(commit.rounds, 1);
+    }
+
+    #[test]
+    fn test_safety_store_restart() {
+        let path = ::std::env::temp_dir().join(format!("bft-restart-{}", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let start = |proposal: u8| {
+            let mut config = BftConfig::new();
+            config
+                .set_safety_store(FileSafetyStore::new(&path))
+                .unwrap();
+            let mut node = SimNode::new(Address::new(vec![0]));
+            node.bft.apply_config(config);
+            assert_eq!(node.bft.process(gen_status(0, &authority)), Ok(()));
+            node.bft.proposal = Some(Target::new(vec![proposal]));
+            node.bft.change_to_step(Step::Prevote);
+            node.drain();
+            node
+        };
+        let prevotes = |node: &mut SimNode| -> Vec<Target> {
+            node.drain()
+                .0
+                .into_iter()
+                .filter_map(|msg| match msg {
+                    CoreOutput::Vote(vote) => Some(vote.proposal),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // sign a prevote and lock on the proposal in round 1
+        let mut node = start(1);
+        node.bft.goto_round(1);
+        assert_eq!(node.bft.transmit_prevote(), Ok(()));
+        assert_eq!(prevotes(&mut node), vec![Target::new(vec![1])]);
+        node.bft.lock_status = Some(LockStatus {
+            proposal: Target::new(vec![1]),
+            round: 1,
+            votes: Vec::new(),
+        });
+        node.bft.save_lock().unwrap();
+        drop(node);
+
+        // the restarted node resumes the round and the lock, and signs the same
+        let mut node = start(2);
+        assert_eq!(node.bft.round, 1);
+        assert_eq!(
+            node.bft
+                .lock_status
+                .as_ref()
+                .map(|lock| lock.proposal.clone()),
+            Some(Target::new(vec![1]))
+        );
+        assert_eq!(node.bft.transmit_prevote(), Ok(()));
+        assert_eq!(prevotes(&mut node), vec![Target::new(vec![1])]);
+
+        // a conflicting vote is refused
+        let conflict = Vote {
+            vote_type: VoteType::Prevote,
+            height: 1,
+            round: 1,
+            proposal: Target::new(vec![2]),
+            voter: Address::new(vec![0]),
+        };
+        assert_eq!(node.bft.sign_vote(&conflict), Err(BftError::ConflictVote));
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_hold_commit_until_content() {
+        let content = CoreInput::ProposalContent(ProposalContent {
+            height: 1,
+            digest: Target::new(vec![7]),
+        });
+        let clock = MockClock::default();
+        let mut node = SimNode::with_clock(Address::new(vec![0]), clock.clone());
+        assert_eq!(
+            node.bft
+                .process(gen_status(0, &[(0, 1), (1, 1), (2, 1), (3, 1), (4, 3)])),
+            Ok(())
+        );
+        node.drain();
+
+        // a quorum of precommits on a proposal which is never received, and a
+        // nil precommit
+        for voter in 1..5 {
+            let precommit = CoreInput::Vote(Vote {
+                vote_type: VoteType::Precommit,
+                height: 1,
+                round: 1,
+                proposal: Target::new(if voter == 1 { vec![] } else { vec![7] }),
+                voter: Address::new(vec![voter]),
+            });
+            assert_eq!(node.bft.process(precommit), Ok(()));
+        }
+        assert_eq!(node.bft.step, Step::Commit);
+        let (msgs, _) = node.drain();
+        assert_eq!(
+            msgs,
+            vec![CoreOutput::RequestProposal {
+                height: 1,
+                round: 1,
+                digest: Target::new(vec![7]),
+            }]
+        );
+
+        // the content of other heights is ignored
+        let mut stale = content.clone();
+        if let CoreInput::ProposalContent(c) = &mut stale {
+            c.height = 2;
+        }
+        assert_eq!(node.bft.process(stale), Ok(()));
+        assert_eq!(node.bft.step, Step::Commit);
+
+        // commit once the content arrives, the consensus time includes the wait
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(node.bft.process(content), Ok(()));
+        assert_eq!(node.bft.step, Step::CommitWait);
+        let (msgs, _) = node.drain();
+        match &msgs[..] {
+            [CoreOutput::Commit(commit)] => {
+                assert_eq!((commit.round, commit.lock_votes.len()), (1, 3));
+                assert_eq!(commit.proposal, Target::new(vec![7]));
+                assert_eq!(commit.precommits.len(), 4);
+                assert_eq!(commit.absent, vec![Address::new(vec![0])]);
+                assert_eq!((commit.precommit_weight, commit.total_weight), (5, 7));
+                assert_eq!((commit.rounds, commit.elapsed), (2, 500));
+            }
+            _ => panic!("Unexpected output {:?}", msgs),
+        }
+    }
+
+    #[test]
+    fn test_reject_proposal() {
+        let prevote = |voter: u8| Vote {
+            vote_type: VoteType::Prevote,
+            height: 1,
+            round: 0,
+            proposal: Target::new(vec![7]),
+            voter: Address::new(vec![voter]),
+        };
+        let mut node = SimNode::new(Address::new(vec![0]));
+        assert_eq!(
+            node.bft
+                .process(gen_status(0, &[(0, 1), (1, 1), (2, 1), (3, 1)])),
+            Ok(())
+        );
+        node.drain();
+        let proposer = node.bft.proposer_of(0).unwrap().unwrap().clone();
+        let proposal = |lock_round: u64, voters: &[u8]| Proposal {
+            height: 1,
+            round: 0,
+            content: Target::new(vec![7]),
+            lock_round: Some(lock_round),
+            lock_votes: voters.iter().map(|v| prevote(*v)).collect(),
+            proposer: proposer.clone(),
+            parent_qc: None,
+        };
+
+        let mut other = proposal(0, &[1, 2, 3]);
+        other.proposer = Address::new(vec![proposer.clone().into_vec()[0] ^ 1]);
+        let fakes = vec![
+            (other, RejectReason::WrongProposer),
+            (proposal(1, &[1, 2, 3]), RejectReason::InvalidLockRound),
+            (proposal(0, &[1, 2]), RejectReason::InsufficientLockWeight),
+            (proposal(0, &[1, 2, 2]), RejectReason::DuplicateLockVote),
+            (proposal(0, &[1, 2, 4]), RejectReason::UnknownLockVoter),
+        ];
+        for (fake, reason) in fakes.into_iter() {
+            assert_eq!(node.bft.process(CoreInput::Proposal(fake.clone())), Ok(()));
+            assert_eq!(node.bft.step, Step::ProposeWait);
+            assert!(node.bft.lock_status.is_none());
+            assert!(node.bft.proposal_contents.is_empty());
+            let (msgs, _) = node.drain();
+            assert_eq!(
+                msgs,
+                vec![CoreOutput::Evidence(Evidence {
+                    reason,
+                    proposal: fake,
+                })]
+            );
+        }
+
+        let mut mismatched = proposal(0, &[1, 2, 3]);
+        mismatched.lock_votes[0].vote_type = VoteType::Precommit;
+        assert_eq!(node.bft.process(CoreInput::Proposal(mismatched)), Ok(()));
+        assert!(node.bft.lock_status.is_none());
+        assert!(node.bft.proposal_contents.is_empty());
+
+        // a valid PoLC is adopted
+        assert_eq!(
+            node.bft
+                .process(CoreInput::Proposal(proposal(0, &[1, 2, 3]))),
+            Ok(())
+        );
+        assert_eq!(node.bft.step, Step::Prevote);
+        assert_eq!(
+            node.bft.lock_status.as_ref().map(|lock| lock.round),
+            Some(0)
+        );
+        assert!(node.bft.proposal_contents.contains(&Target::new(vec![7])));
+    }
+
+    #[test]
+    fn test_future_round_proposal() {
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let proposers = proposers(&authority, 4);
+        // a delayed node which proposes in none of round 0 and round 2
+        let address = (0..4)
+            .map(|i| Address::new(vec![i]))
+            .find(|a| *a != proposers[0] && *a != proposers[2])
+            .unwrap();
+        let proposal = |round: u64| Proposal {
+            height: 1,
+            round,
+            content: Target::new(vec![round as u8]),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: proposers[round as usize].clone(),
+            parent_qc: None,
+        };
+
+        let mut node = SimNode::new(address.clone());
+        start(&mut node, &authority, BftConfig::new());
+
+        // the proposal of round 2 arrives before the one of round 0
+        assert_eq!(node.bft.process(CoreInput::Proposal(proposal(2))), Ok(()));
+        assert_eq!(node.bft.step, Step::ProposeWait);
+        assert_eq!(node.bft.proposal, None);
+        assert_eq!(node.bft.process(CoreInput::Proposal(proposal(0))), Ok(()));
+        assert_eq!(node.bft.step, Step::Prevote);
+        assert_eq!(
+            votes(node.drain().0, VoteType::Prevote),
+            vec![(0, Target::new(vec![0]))]
+        );
+
+        // the ones of round 1 and a round out of the window are saved or
+        // dropped while the node prevotes
+        assert_eq!(node.bft.process(CoreInput::Proposal(proposal(1))), Ok(()));
+        let mut far = proposal(0);
+        far.round = FUTURE_ROUND_WINDOW + 1;
+        far.proposer = node.bft.proposer_of(far.round).unwrap().unwrap().clone();
+        assert_eq!(node.bft.process(CoreInput::Proposal(far)), Ok(()));
+        assert_eq!(node.bft.step, Step::Prevote);
+        assert_eq!(
+            node.bft
+                .future_proposals
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        // the node catches up to round 2 and prevotes the saved proposal at once
+        for voter in (0..4).map(|i| Address::new(vec![i])) {
+            if voter != address {
+                let round_change = RoundChange {
+                    height: 1,
+                    round: 2,
+                    voter,
+                };
+                assert_eq!(
+                    node.bft.process(CoreInput::RoundChange(round_change)),
+                    Ok(())
+                );
+            }
+        }
+        assert_eq!(node.bft.round, 2);
+        assert_eq!(node.bft.step, Step::Prevote);
+        assert_eq!(
+            votes(node.drain().0, VoteType::Prevote),
+            vec![(2, Target::new(vec![2]))]
+        );
+        assert!(node.bft.future_proposals.is_empty());
+
+        // the node jumps to round 3 on +2/3 prevotes and takes the saved proposal
+        assert_eq!(node.bft.process(CoreInput::Proposal(proposal(3))), Ok(()));
+        for voter in (0..4).map(|i| Address::new(vec![i])) {
+            if voter != address {
+                let prevote = Vote {
+                    vote_type: VoteType::Prevote,
+                    height: 1,
+                    round: 3,
+                    proposal: Target::new(vec![3]),
+                    voter,
+                };
+                assert_eq!(node.bft.process(CoreInput::Vote(prevote)), Ok(()));
+            }
+        }
+        assert_eq!(node.bft.round, 3);
+        assert_eq!(node.bft.proposal, Some(Target::new(vec![3])));
+        assert!(node.bft.future_proposals.is_empty());
+    }
+
+    #[test]
+    fn test_late_precommits() {
+        let authority = [(0, 1), (1, 9), (2, 1), (3, 1), (4, 1)];
+        let precommit = |voter: u8, proposal: Vec<u8>| {
+            CoreInput::Vote(Vote {
+                vote_type: VoteType::Precommit,
+                height: 1,
+                round: 0,
+                proposal: Target::new(proposal),
+                voter: Address::new(vec![voter]),
+            })
+        };
+        let updates = |node: &mut SimNode| -> Vec<Commit> {
+            node.drain()
+                .0
+                .into_iter()
+                .filter_map(|msg| match msg {
+                    CoreOutput::CommitUpdate(commit) => Some(commit),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut node = SimNode::new(Address::new(vec![0]));
+        assert_eq!(node.bft.process(gen_status(0, &authority)), Ok(()));
+        assert_eq!(
+            node.bft
+                .process(CoreInput::ProposalContent(ProposalContent {
+                    height: 1,
+                    digest: Target::new(vec![7]),
+                })),
+            Ok(())
+        );
+        assert_eq!(node.bft.process(precommit(1, vec![7])), Ok(()));
+        assert_eq!(node.bft.step, Step::CommitWait);
+        node.drain();
+
+        // a late nil precommit extends the precommits only
+        assert_eq!(node.bft.process(precommit(2, vec![])), Ok(()));
+        let update = updates(&mut node);
+        assert_eq!(update.len(), 1);
+        assert_eq!(
+            (update[0].precommits.len(), update[0].lock_votes.len()),
+            (2, 1)
+        );
+        assert_eq!(update[0].precommit_weight, 9);
+        assert_eq!(update[0].absent.len(), 3);
+        assert_eq!(node.bft.process(precommit(2, vec![])), Ok(()));
+        assert!(updates(&mut node).is_empty());
+
+        // keep collecting at the next height until its proposal
+        assert_eq!(node.bft.process(gen_status(1, &authority)), Ok(()));
+        assert_eq!(node.bft.process(precommit(3, vec![7])), Ok(()));
+        let update = updates(&mut node);
+        assert_eq!(update.len(), 1);
+        assert_eq!(
+            (update[0].precommits.len(), update[0].lock_votes.len()),
+            (3, 2)
+        );
+        assert_eq!(update[0].precommit_weight, 10);
+        assert_eq!(
+            update[0].absent,
+            vec![Address::new(vec![0]), Address::new(vec![4])]
+        );
+
+        let proposal = CoreInput::Proposal(Proposal {
+            height: 2,
+            round: 0,
+            content: Target::new(vec![8]),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: Address::new(vec![1]),
+            parent_qc: None,
+        });
+        assert_eq!(node.bft.process(proposal), Ok(()));
+        assert_eq!(node.bft.process(precommit(4, vec![7])), Ok(()));
+        assert!(updates(&mut node).is_empty());
+    }
+
+    #[test]
+    fn test_past_height_authorities() {
+        let vote = |vote_type: VoteType, voter: u8, proposal: Vec<u8>| {
+            CoreInput::Vote(Vote {
+                vote_type,
+                height: 1,
+                round: 0,
+                proposal: Target::new(proposal),
+                voter: Address::new(vec![voter]),
+            })
+        };
+        let commit_height_one = |config: BftConfig| -> SimNode {
+            let mut node = SimNode::new(Address::new(vec![0]));
+            node.bft.apply_config(config);
+            let status = gen_status(0, &[(0, 1), (1, 9), (2, 1), (3, 1)]);
+            assert_eq!(node.bft.process(status), Ok(()));
+            assert_eq!(
+                node.bft
+                    .process(CoreInput::ProposalContent(ProposalContent {
+                        height: 1,
+                        digest: Target::new(vec![7]),
+                    })),
+                Ok(())
+            );
+            // the node prevotes on the proposal before the commit
+            let proposer = node.bft.proposer_of(0).unwrap().unwrap().clone();
+            let proposal = CoreInput::Proposal(Proposal {
+                height: 1,
+                round: 0,
+                content: Target::new(vec![7]),
+                lock_round: None,
+                lock_votes: Vec::new(),
+                proposer,
+                parent_qc: None,
+            });
+            assert_eq!(node.bft.process(proposal), Ok(()));
+            assert_eq!(
+                node.bft.process(vote(VoteType::Precommit, 1, vec![7])),
+                Ok(())
+            );
+            assert_eq!(node.bft.step, Step::CommitWait);
+
+            // the authorities change at height 2
+            let status = gen_status(1, &[(0, 1), (4, 1), (5, 1), (6, 1)]);
+            assert_eq!(node.bft.process(status), Ok(()));
+            node.drain();
+            node
+        };
+        let retransmits = |node: &mut SimNode| -> Vec<Address> {
+            node.drain()
+                .0
+                .into_iter()
+                .filter_map(|msg| match msg {
+                    CoreOutput::SendTo { to, .. } => Some(to),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut node = commit_height_one(BftConfig::new());
+        // a late precommit is weighed with the authorities of height 1
+        assert_eq!(
+            node.bft.process(vote(VoteType::Precommit, 2, vec![7])),
+            Ok(())
+        );
+        let updates: Vec<Commit> = node
+            .drain()
+            .0
+            .into_iter()
+            .filter_map(|msg| match msg {
+                CoreOutput::CommitUpdate(commit) => Some(commit),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].precommit_weight, 10);
+
+        // the lagging authority of height 1 is helped with the sent prevote,
+        // a new one is not
+        assert_eq!(node.bft.process(vote(VoteType::Prevote, 3, vec![])), Ok(()));
+        assert_eq!(retransmits(&mut node), vec![Address::new(vec![3])]);
+        assert_eq!(node.bft.process(vote(VoteType::Prevote, 4, vec![])), Ok(()));
+        assert!(retransmits(&mut node).is_empty());
+
+        // the authorities of height 1 are dropped out of the window
+        let status = gen_status(2, &[(0, 1), (4, 1), (5, 1), (6, 1)]);
+        assert_eq!(node.bft.process(status), Ok(()));
+        assert!(!node.bft.past_vote_weights.contains_key(&1));
+        assert!(node.bft.past_vote_weights.contains_key(&2));
+
+        let mut config = BftConfig::new();
+        config.set_authority_window(0);
+        let mut node = commit_height_one(config);
+        assert!(node.bft.past_vote_weights.is_empty());
+        assert_eq!(node.bft.process(vote(VoteType::Prevote, 3, vec![])), Ok(()));
+        assert!(retransmits(&mut node).is_empty());
+    }
+
+    #[test]
+    fn test_empty_block_policy() {
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let proposer = proposers(&authority, 1).remove(0);
+        let empty = Target::new(vec![0xee]);
+
+        // start the proposer of round 0 without a feed
+        let start_proposer = |policy: EmptyBlockPolicy, allow: bool| {
+            let mut node = SimNode::new(proposer.clone());
+            node.bft.msg_sender.1 = allow;
+            let mut config = BftConfig::new();
+            config.set_empty_block_policy(policy);
+            let (msgs, timers) = start(&mut node, &authority, config);
+            let now = node.bft.params.clock.now();
+            let propose_wait: Vec<Duration> = timers
+                .into_iter()
+                .filter_map(|cmd| match cmd {
+                    TimerCmd::Set(_, info) if info.step == Step::ProposeWait => {
+                        Some(info.timeval.duration_since(now))
+                    }
+                    _ => None,
+                })
+                .collect();
+            (node, msgs, propose_wait)
+        };
+        let get_proposal = CoreOutput::GetProposalRequest(1);
+
+        let (node, msgs, propose_wait) = start_proposer(EmptyBlockPolicy::Wait, true);
+        assert_eq!(node.bft.step, Step::ProposeWait);
+        assert_eq!(msgs, vec![get_proposal.clone()]);
+        assert_eq!(propose_wait, vec![node.bft.params.timer.get_propose()]);
+
+        let bound = Duration::from_millis(100);
+        let (node, msgs, propose_wait) = start_proposer(EmptyBlockPolicy::WaitFor(bound), true);
+        assert_eq!(node.bft.step, Step::ProposeWait);
+        assert_eq!(msgs, vec![get_proposal.clone()]);
+        assert_eq!(propose_wait, vec![bound]);
+
+        // the empty block is proposed at once if the application allows it
+        let (node, msgs, _) = start_proposer(EmptyBlockPolicy::Propose(empty.clone()), true);
+        assert_eq!(node.bft.step, Step::Prevote);
+        assert!(msgs.iter().any(|msg| match msg {
+            CoreOutput::Proposal(p) => p.content == empty,
+            _ => false,
+        }));
+        assert!(node.bft.has_content(&empty));
+        let (node, msgs, propose_wait) = start_proposer(EmptyBlockPolicy::Propose(empty), false);
+        assert_eq!(node.bft.step, Step::ProposeWait);
+        assert_eq!(msgs, vec![get_proposal.clone()]);
+        assert_eq!(propose_wait.len(), 1);
+
+        // the round fails fast without a proposal
+        let (node, msgs, propose_wait) = start_proposer(EmptyBlockPolicy::Skip, true);
+        assert_eq!(node.bft.step, Step::Prevote);
+        assert!(propose_wait.is_empty());
+        assert_eq!(msgs[0], get_proposal);
+        assert!(msgs.iter().any(|msg| match msg {
+            CoreOutput::RoundChange(rc) => rc.round == 1,
+            _ => false,
+        }));
+        assert!(msgs.iter().any(|msg| match msg {
+            CoreOutput::Vote(v) => v.vote_type == VoteType::Prevote && v.proposal.is_nil(),
+            _ => false,
+        }));
+
+        // the round change of the proposer makes the others prevote nil, and
+        // they leave the round before their propose timers fire
+        let round_changes = |msgs: &[CoreOutput]| -> Vec<RoundChange> {
+            msgs.iter()
+                .filter_map(|msg| match msg {
+                    CoreOutput::RoundChange(rc) => Some(rc.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let skip = round_changes(&msgs).remove(0);
+        let mut others: Vec<SimNode> = (0..4)
+            .map(|i| Address::new(vec![i]))
+            .filter(|a| *a != proposer)
+            .take(2)
+            .map(|address| {
+                let mut node = SimNode::new(address);
+                start(&mut node, &authority, BftConfig::new());
+                assert_eq!(node.bft.step, Step::ProposeWait);
+                node
+            })
+            .collect();
+        let mut sent = Vec::new();
+        for node in others.iter_mut() {
+            assert_eq!(
+                node.bft.process(CoreInput::RoundChange(skip.clone())),
+                Ok(())
+            );
+            assert_eq!(node.bft.step, Step::Prevote);
+            let msgs = node.drain().0;
+            assert_eq!(
+                votes(msgs.clone(), VoteType::Prevote),
+                vec![(0, Target::new(Vec::new()))]
+            );
+            sent.push(round_changes(&msgs).remove(0));
+        }
+        assert_eq!(
+            others[0]
+                .bft
+                .process(CoreInput::RoundChange(sent[1].clone())),
+            Ok(())
+        );
+        assert_eq!(others[0].bft.round, 1);
+    }
+
+    #[test]
+    fn test_prepare_proposal() {
+        // the heavy voter never proposes
+        let status = CoreInput::Status(Status {
+            height: 0,
+            interval: None,
+            authority_list: [(0, 1), (1, 9), (2, 1), (3, 1)]
+                .iter()
+                .map(|(addr, weight)| {
+                    let mut node = Node::new(Address::new(vec![*addr]));
+                    node.set_propose_weight(if *addr == 1 { 0 } else { 1 });
+                    node.set_vote_weight(*weight);
+                    node
+                })
+                .collect(),
+        });
+        let precommit = CoreInput::Vote(Vote {
+            vote_type: VoteType::Precommit,
+            height: 1,
+            round: 0,
+            proposal: Target::new(vec![7]),
+            voter: Address::new(vec![1]),
+        });
+        // commit height 1 at once with the precommit of the heavy authority
+        let commit = |node: &mut SimNode| -> Vec<CoreOutput> {
+            assert_eq!(node.bft.process(status.clone()), Ok(()));
+            assert_eq!(
+                node.bft
+                    .process(CoreInput::ProposalContent(ProposalContent {
+                        height: 1,
+                        digest: Target::new(vec![7]),
+                    })),
+                Ok(())
+            );
+            node.drain();
+            assert_eq!(node.bft.process(precommit.clone()), Ok(()));
+            assert_eq!(node.bft.step, Step::CommitWait);
+            node.drain().0
+        };
+        let prepare = CoreOutput::PrepareProposal { height: 2 };
+
+        // only the predicted proposer of height 2 prepares after the commit
+        let mut prepared = Vec::new();
+        for i in [0, 2, 3].iter() {
+            let mut node = SimNode::new(Address::new(vec![*i]));
+            let msgs = commit(&mut node);
+            let proposer = node.bft.proposer_at(2, 0).unwrap().unwrap().clone();
+            assert_eq!(msgs.contains(&prepare), proposer == node.bft.params.address);
+            if msgs.contains(&prepare) {
+                prepared.push(*i);
+                assert!(matches!(
+                    msgs.last(),
+                    Some(CoreOutput::PrepareProposal { .. })
+                ));
+            }
+        }
+        assert_eq!(prepared.len(), 1);
+
+        // the one with the feed of height 2 already does not ask again
+        let mut node = SimNode::new(Address::new(vec![prepared[0]]));
+        let feed = CoreInput::Feed(Feed {
+            height: 2,
+            proposal: Target::new(vec![8]),
+        });
+        assert_eq!(node.bft.process(feed), Ok(()));
+        assert!(!commit(&mut node).contains(&prepare));
+    }
+
+    #[test]
+    fn test_fast_commit() {
+        let authority = [(0, 1), (1, 1), (2, 1), (3, 1)];
+        let proposers = proposers(&authority, 3);
+        let address = (0..4)
+            .map(|i| Address::new(vec![i]))
+            .find(|a| !proposers.contains(a))
+            .unwrap();
+        let others: Vec<Address> = (0..4)
+            .map(|i| Address::new(vec![i]))
+            .filter(|a| *a != address)
+            .collect();
+        let proposal = |round: u64| Proposal {
+            height: 1,
+            round,
+            content: Target::new(vec![round as u8 + 1]),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: proposers[round as usize].clone(),
+            parent_qc: None,
+        };
+        let prevote = |voter: &Address, target: Vec<u8>| {
+            CoreInput::Vote(Vote {
+                vote_type: VoteType::Prevote,
+                height: 1,
+                round: 0,
+                proposal: Target::new(target),
+                voter: voter.clone(),
+            })
+        };
+        // start the node which prevotes the proposal of round 0
+        let start_voter = || {
+            let mut node = SimNode::new(address.clone());
+            let mut config = BftConfig::new();
+            config.set_fast_commit(Some((1, 1))).unwrap();
+            start(&mut node, &authority, config);
+            assert_eq!(node.bft.process(CoreInput::Proposal(proposal(0))), Ok(()));
+            #[cfg(feature = "async_verify")]
+            {
+                let verify_resp = CoreInput::VerifyResp(VerifyResp {
+                    is_pass: true,
+                    proposal: Target::new(vec![1]),
+                });
+                assert_eq!(node.bft.process(verify_resp), Ok(()));
+            }
+            assert_eq!(
+                votes(node.drain().0, VoteType::Prevote),
+                vec![(0, Target::new(vec![1]))]
+            );
+            node
+        };
+
+        // +2/3 prevotes go on to precommit
+        let mut node = start_voter();
+        for voter in others[..2].iter() {
+            assert_eq!(node.bft.process(prevote(voter, vec![1])), Ok(()));
+        }
+        assert_eq!(node.bft.step, Step::PrevoteWait);
+        let (_, timers) = node.drain();
+        for cmd in timers {
+            if let TimerCmd::Set(_, info) = cmd {
+                if info.step == Step::PrevoteWait {
+                    assert_eq!(node.bft.timeout_process(&info), Ok(()));
+                }
+            }
+        }
+        assert_eq!(
+            votes(node.drain().0, VoteType::Precommit),
+            vec![(0, Target::new(vec![1]))]
+        );
+
+        // the late prevote of the last authority commits on the fast path
+        assert_eq!(node.bft.process(prevote(&others[2], vec![1])), Ok(()));
+        assert_eq!(node.bft.step, Step::CommitWait);
+        match &node.drain().0[..] {
+            [CoreOutput::Commit(commit)] => {
+                assert!(commit.fast_path);
+                assert_eq!(commit.proposal, Target::new(vec![1]));
+                assert_eq!(commit.lock_votes.len(), 4);
+                assert!(commit
+                    .lock_votes
+                    .iter()
+                    .all(|v| v.vote_type == VoteType::Prevote));
+                assert_eq!(commit.precommit_weight, 4);
+                assert!(commit.absent.is_empty());
+            }
+            msgs => panic!("Unexpected output {:?}", msgs),
+        }
+
+        // a node which prevoted in round 0 keeps prevoting the proposal, until
+        // the prevotes of round 0 show that the fast path failed
+        let mut node = start_voter();
+        assert_eq!(node.bft.process(prevote(&others[0], vec![])), Ok(()));
+        for round in 1..3 {
+            for voter in others.iter() {
+                let round_change = RoundChange {
+                    height: 1,
+                    round,
+                    voter: voter.clone(),
+                };
+                assert_eq!(
+                    node.bft.process(CoreInput::RoundChange(round_change)),
+                    Ok(())
+                );
+            }
+            assert_eq!(
+                node.bft.process(CoreInput::Proposal(proposal(round))),
+                Ok(())
+            );
+            let expected = if round == 1 { 1 } else { 3 };
+            assert_eq!(
+                votes(node.drain().0, VoteType::Prevote),
+                vec![(round, Target::new(vec![expected]))]
+            );
+            if round == 1 {
+                assert_eq!(node.bft.process(prevote(&others[1], vec![])), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_commit_equivocation() {
+        let mut config = config(3, 1);
+        config.observer = 0;
+        config.max_round = 12;
+        config.fast_commit = Some((1, 1));
+        let mut cluster = Cluster::new(config);
+        // the first height that the byzantine node proposes in round 0
+        let byzantine = cluster.authority[3].address.clone();
+        let height = (1..)
+            .find(|h| cluster.nodes[0].bft.proposer_at(*h, 0).unwrap() == Some(&byzantine))
+            .unwrap();
+        if height > 1 {
+            cluster.config.max_height = height - 1;
+            cluster.run(&[]).unwrap();
+        }
+        assert!(cluster.nodes.iter().all(|node| node.bft.height == height));
+
+        // two nodes prevote a proposal and the third one another in round 0,
+        // the byzantine node stays silent then
+        for (to, content) in [(0, 0xb0), (1, 0xb0), (2, 0xb1)].iter() {
+            let content = Target::new(vec![*content, height as u8]);
+            cluster
+                .proposed
+                .entry(height)
+                .or_default()
+                .push(content.clone());
+            cluster.pending.push(Event::Msg {
+                to: *to,
+                msg: CoreInput::Proposal(Proposal {
+                    height,
+                    round: 0,
+                    content,
+                    lock_round: None,
+                    lock_votes: Vec::new(),
+                    proposer: byzantine.clone(),
+                    parent_qc: None,
+                }),
+            });
+        }
+        cluster.config.max_height = height;
+        cluster.run(&[]).unwrap();
+        assert_eq!(cluster.commits()[&height].len(), 3);
+    }
+
+    #[test]
+    fn test_fair_schedule_commits() {
+        let mut cluster = Cluster::new(config(4, 0));
+        cluster.run(&[]).unwrap();
+        for height in 1..=2 {
+            // the observer commits as well
+            assert_eq!(cluster.commits()[&height].len(), 5);
+        }
+    }
+
+    /// Bounded model checking of 4 nodes within 2 rounds. Every order of the
+    /// first deliveries is explored up to the depth, 4 by default or set by
+    /// `BFT_MODEL_CHECK_DEPTH`, and each order is finished by fair delivery. It
+    /// takes a while, run it by `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_model_check() {
+        let depth = std::env::var("BFT_MODEL_CHECK_DEPTH")
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(4);
+        let config = SimConfig {
+            honest: 4,
+            byzantine: 0,
+            observer: 0,
+            max_height: 1,
+            max_round: 2,
+            max_steps: 500,
+            retransmit: false,
+            fast_commit: None,
+        };
+        assert!(model_check(&config, depth).unwrap() > 0);
+    }
+}
@@ -0,0 +1,1352 @@
+use crate::{
+    algorithm::{Step, FUTURE_ROUND_WINDOW, INIT_HEIGHT},
+    config::BftConfig,
+    error::BftError,
+    params::BftParams,
+    rand::get_index,
+    safety::{SafetyState, SafetyStore},
+    timer::{TimeoutInfo, TimerCmd, TimerId, WaitTimer},
+    types::*,
+    voteset::{RoundChangeCollector, VoteSet},
+    FromCore,
+};
+
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
+use log::{debug, error, info, trace, warn};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CHAIN_ID: ChainId = 0;
+const INIT_ROUND: u64 = 0;
+const PROPOSAL_TIMES_COEF: u64 = 10;
+
+/// A proposal in the chain, it is the height and the proposal.
+type BlockId = (u64, Target);
+
+/// A proposal of a view, it is the height, the view and the proposal.
+type ViewBlock = (u64, u64, Target);
+
+/// BFT state machine of the chained mode.
+///
+/// The views are numbered globally, the `round` of a proposal or a vote is its
+/// view, and the node only moves to a higher view. The proposal of a height
+/// carries the quorum certificate of its parent at the height before, so the
+/// votes on it go on with the parent as well. Once a proposal is certified, the
+/// node enters the next height in the next view at once without waiting for
+/// `Status`. The parent of a certified proposal is locked, and the grandparent
+/// is committed if the three proposals are of consecutive views.
+pub(crate) struct Chained<T> {
+    msg_sender: T,
+    timer_seter: Sender<TimerCmd>,
+    timer_seq: u64,
+    running: bool,
+
+    height: u64,
+    // the current view
+    round: u64,
+    // the view the node enters the height at, the view timer doubles every
+    // view after it
+    height_round: u64,
+    // the last committed height, by the engine or by `Status`
+    commit_height: u64,
+    // the last view voted and proposed, the node does each once in a view
+    voted: Option<u64>,
+    proposed: Option<u64>,
+    feed: Option<Feed>,
+    // the parent of each proposal received
+    parents: HashMap<ViewBlock, ViewBlock>,
+    // the certificate of each certified proposal
+    certs: HashMap<ViewBlock, QuorumCert>,
+    high_qc: Option<QuorumCert>,
+    locked_qc: Option<QuorumCert>,
+    // the proposals decided by the three-chain, they are committed in order
+    decided: BTreeMap<u64, QuorumCert>,
+    // the proposals whose contents are in the application
+    contents: HashSet<BlockId>,
+    requested_contents: HashSet<BlockId>,
+    votes: HashMap<(u64, u64), VoteSet>,
+    round_changes: RoundChangeCollector,
+    authority_list: Vec<Address>,
+    propose_weight: Vec<u64>,
+    vote_weight_map: HashMap<Address, u64>,
+    // the time the node enters each height
+    htimes: BTreeMap<u64, Instant>,
+    params: BftParams,
+    // the last vote and the lock are saved to sign no conflicting vote after a
+    // restart
+    safety_store: Option<Box<dyn SafetyStore>>,
+    safety_state: SafetyState,
+}
+
+impl<T> Chained<T>
+where
+    T: FromCore + Send + 'static,
+{
+    /// A function to start a chained state machine.
+    pub(crate) fn start(s: T, r: Receiver<CoreInput>, local_address: Address, config: BftConfig) {
+        let (engine2timer, timer4engine) = unbounded();
+        let (timer2engine, engine4timer) = unbounded();
+
+        let timer = WaitTimer::new(timer2engine, timer4engine, Arc::clone(&config.clock));
+        thread::spawn(move || timer.start());
+
+        let mut engine = Chained::initialize(s, engine2timer, local_address);
+        engine.apply_config(config);
+        thread::spawn(move || loop {
+            select! {
+                recv(engine4timer) -> msg => {
+                    if let Ok(info) = msg {
+                        engine.handle_timeout(&info);
+                    }
+                }
+                recv(r) -> msg => match msg {
+                    Ok(msg) => engine.handle_input(msg),
+                    // the core is dropped
+                    Err(_) => return,
+                },
+            }
+        });
+    }
+
+    pub(crate) fn initialize(s: T, ts: Sender<TimerCmd>, local_address: Address) -> Self {
+        info!("BFT Chained State Machine Launched.");
+        Chained {
+            msg_sender: s,
+            timer_seter: ts,
+            timer_seq: 0,
+            running: false,
+
+            height: INIT_HEIGHT,
+            round: INIT_ROUND,
+            height_round: INIT_ROUND,
+            commit_height: INIT_HEIGHT,
+            voted: None,
+            proposed: None,
+            feed: None,
+            parents: HashMap::new(),
+            certs: HashMap::new(),
+            high_qc: None,
+            locked_qc: None,
+            decided: BTreeMap::new(),
+            contents: HashSet::new(),
+            requested_contents: HashSet::new(),
+            votes: HashMap::new(),
+            round_changes: RoundChangeCollector::new(),
+            authority_list: Vec::new(),
+            propose_weight: Vec::new(),
+            vote_weight_map: HashMap::new(),
+            htimes: BTreeMap::new(),
+            params: BftParams::new(local_address),
+            safety_store: None,
+            safety_state: SafetyState::default(),
+        }
+    }
+
+    /// A function to apply the config to the state machine.
+    pub(crate) fn apply_config(&mut self, config: BftConfig) {
+        self.params.clock = config.clock;
+        self.params.report_evidence = config.report_evidence;
+        if let Some((store, state)) = config.safety {
+            info!("Load safety state of height {:?}", state.height);
+            self.voted = state.votes.iter().map(|vote| vote.round).max();
+            self.locked_qc = state.locked_qc.clone();
+            self.safety_store = Some(store);
+            self.safety_state = state;
+        }
+    }
+
+    /// A function to handle an input of the main loop. The inputs except `Start`
+    /// are dropped while the state machine is paused.
+    pub(crate) fn handle_input(&mut self, msg: CoreInput) {
+        if !self.running {
+            if msg == CoreInput::Start {
+                info!("BFT go on running");
+                self.running = true;
+            }
+            return;
+        }
+
+        if msg == CoreInput::Pause {
+            info!("BFT pause");
+            self.running = false;
+        } else if let Err(e) = self.process(msg) {
+            error!("Process message error {:?}", e);
+        }
+    }
+
+    /// A function to handle a timeout of the main loop.
+    pub(crate) fn handle_timeout(&mut self, info: &TimeoutInfo) {
+        if !self.running {
+            return;
+        }
+        if let Err(e) = self.timeout_process(info) {
+            error!("Process timeout {:?} error {:?}", info, e);
+        }
+    }
+
+    #[inline]
+    fn send_bft_msg(&mut self, msg: CoreOutput) {
+        if let Err(e) = self.msg_sender.send_msg(msg) {
+            error!("Send message error {:?}", e);
+        }
+    }
+
+    #[inline]
+    fn send_timer_cmd(&self, cmd: TimerCmd) {
+        if self.timer_seter.send(cmd).is_err() {
+            error!("Send timer command error, the timer is stopped");
+        }
+    }
+
+    /// A function to set the timer of the view, the node asks for the next
+    /// view once it fires. The timer doubles every view of the height.
+    fn set_view_timer(&mut self) {
+        let coef = self
+            .round
+            .saturating_sub(self.height_round)
+            .min(PROPOSAL_TIMES_COEF);
+        let duration: Duration = self.params.timer.get_propose() * 2u32.pow(coef as u32);
+        trace!("Set view timer for {:?}", duration);
+        self.timer_seq = self.timer_seq.wrapping_add(1);
+        self.send_timer_cmd(TimerCmd::Set(
+            TimerId {
+                chain_id: DEFAULT_CHAIN_ID,
+                seq: self.timer_seq,
+            },
+            TimeoutInfo {
+                timeval: self.params.clock.now() + duration,
+                chain_id: DEFAULT_CHAIN_ID,
+                height: self.height,
+                round: self.round,
+                step: Step::ProposeWait,
+            },
+        ));
+    }
+
+    #[inline]
+    fn total_weight(&self) -> u128 {
+        self.vote_weight_map.values().map(|w| u128::from(*w)).sum()
+    }
+
+    #[inline]
+    fn cal_above_threshold(&self, count: u64) -> bool {
+        u128::from(count) * 3 > self.total_weight() * 2
+    }
+
+    #[inline]
+    fn local_weight(&self) -> u64 {
+        self.vote_weight_map
+            .get(&self.params.address)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    #[inline]
+    fn is_observer(&self) -> bool {
+        self.local_weight() == 0
+    }
+
+    /// A function to get the proposer of the height and round.
+    fn proposer_of(&self, height: u64, round: u64) -> Option<&Address> {
+        if self.authority_list.is_empty() {
+            return None;
+        }
+        get_index(height.wrapping_add(round), &self.propose_weight)
+            .and_then(|index| self.authority_list.get(index))
+    }
+
+    #[inline]
+    fn is_proposer_of(&self, height: u64, round: u64) -> bool {
+        self.proposer_of(height, round) == Some(&self.params.address)
+    }
+
+    /// A function to enter the view at the height. The proposer of the view
+    /// proposes on the highest certificate, and the proposer of the next view
+    /// at the next height is asked to prepare its proposal.
+    fn enter_view(&mut self, height: u64, round: u64) -> Result<(), BftError> {
+        self.round_changes.remove_stale(round);
+        let commit_height = self.commit_height;
+        self.votes
+            .retain(|(h, r), _| *h > commit_height && *r >= round);
+        if height != self.height {
+            self.height_round = round;
+        }
+        self.height = height;
+        self.round = round;
+        let now = self.params.clock.now();
+        self.htimes.entry(height).or_insert(now);
+        self.send_timer_cmd(TimerCmd::CancelBefore {
+            chain_id: DEFAULT_CHAIN_ID,
+            height,
+            round,
+        });
+        self.set_view_timer();
+        info!("Enter height {:?}, round {:?}", height, round);
+
+        if self.is_observer() {
+            return Ok(());
+        }
+        let has_feed = |feed: &Option<Feed>, height: u64| {
+            feed.as_ref().is_some_and(|feed| feed.height >= height)
+        };
+        if self.is_proposer_of(height, round) && !has_feed(&self.feed, height) {
+            self.send_bft_msg(CoreOutput::GetProposalRequest(height));
+        }
+        let next = height.checked_add(1).ok_or(BftError::Overflow)?;
+        let next_round = round.checked_add(1).ok_or(BftError::Overflow)?;
+        if round == self.height_round
+            && self.is_proposer_of(next, next_round)
+            && !has_feed(&self.feed, next)
+        {
+            self.send_bft_msg(CoreOutput::PrepareProposal { height: next });
+        }
+        self.try_propose()
+    }
+
+    /// A function to propose the feed of the height on the certificate of its
+    /// parent. The proposal of the height after the committed one has no
+    /// certificate.
+    fn try_propose(&mut self) -> Result<(), BftError> {
+        if self.proposed >= Some(self.round) || !self.is_proposer_of(self.height, self.round) {
+            return Ok(());
+        }
+        let parent_qc = if self.height == self.commit_height.saturating_add(1) {
+            None
+        } else {
+            match self
+                .high_qc
+                .clone()
+                .filter(|qc| qc.height.checked_add(1) == Some(self.height))
+            {
+                Some(qc) => Some(qc),
+                None => return Ok(()),
+            }
+        };
+        let feed = match self.feed.clone().filter(|feed| feed.height == self.height) {
+            Some(feed) => feed,
+            None => return Ok(()),
+        };
+
+        info!(
+            "Transmit proposal at height {:?}, round {:?}",
+            self.height, self.round
+        );
+        let proposal = Proposal {
+            height: self.height,
+            round: self.round,
+            content: feed.proposal,
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: self.params.address.clone(),
+            parent_qc,
+        };
+        self.proposed = Some(self.round);
+        self.send_bft_msg(CoreOutput::Proposal(proposal.clone()));
+        self.vote_proposal(proposal)
+    }
+
+    fn handle_proposal(&mut self, proposal: Proposal) -> Result<(), BftError> {
+        if proposal.round < self.round {
+            debug!(
+                "Receive a stale proposal of height {:?}, round {:?}",
+                proposal.height, proposal.round
+            );
+            return Ok(());
+        }
+        if self.proposer_of(proposal.height, proposal.round) != Some(&proposal.proposer) {
+            self.reject_proposal(proposal, RejectReason::WrongProposer);
+            return Ok(());
+        }
+
+        match proposal.parent_qc.clone() {
+            Some(qc) => {
+                if qc.height.checked_add(1) != Some(proposal.height)
+                    || qc.round >= proposal.round
+                    || !self.check_qc(&qc)
+                {
+                    self.reject_proposal(proposal, RejectReason::InvalidQuorumCert);
+                    return Ok(());
+                }
+                // the certificate may lead the node to the height of the proposal
+                self.update_qc(qc)?;
+            }
+            None if proposal.height == self.commit_height.saturating_add(1) => {}
+            None => {
+                // the parent may be committed by the others only
+                warn!(
+                    "Receive a proposal of height {:?} without the certificate of its parent",
+                    proposal.height
+                );
+                return Ok(());
+            }
+        }
+
+        if proposal.round != self.round {
+            debug!(
+                "Receive a proposal of height {:?}, round {:?}, self round is {:?}",
+                proposal.height, proposal.round, self.round
+            );
+            return Ok(());
+        }
+        self.vote_proposal(proposal)
+    }
+
+    fn reject_proposal(&mut self, proposal: Proposal, reason: RejectReason) {
+        warn!(
+            "Reject the proposal at height {:?}, round {:?}, from {:?}, for {:?}",
+            proposal.height, proposal.round, proposal.proposer, reason
+        );
+        if self.params.report_evidence {
+            self.send_bft_msg(CoreOutput::Evidence(Evidence { reason, proposal }));
+        }
+    }
+
+    /// A function to check a quorum certificate. The votes must be generic
+    /// votes on the proposal at its height and round, from distinct
+    /// authorities with more than 2/3 of the vote weight.
+    fn check_qc(&self, qc: &QuorumCert) -> bool {
+        let mut voters = HashSet::new();
+        let mut weight = 0u64;
+        for vote in qc.votes.iter() {
+            if vote.vote_type != VoteType::Generic
+                || vote.height != qc.height
+                || vote.round != qc.round
+                || vote.proposal != qc.proposal
+                || !voters.insert(&vote.voter)
+            {
+                return false;
+            }
+            match self.vote_weight_map.get(&vote.voter) {
+                Some(w) if *w > 0 => weight = weight.saturating_add(*w),
+                _ => return false,
+            }
+        }
+        self.cal_above_threshold(weight)
+    }
+
+    /// A function to vote on a proposal of the view. The proposal must extend
+    /// the locked one, or its parent is certified in a view after the lock.
+    fn vote_proposal(&mut self, proposal: Proposal) -> Result<(), BftError> {
+        self.contents
+            .insert((proposal.height, proposal.content.clone()));
+        if let Some(qc) = proposal.parent_qc.as_ref() {
+            self.parents.insert(
+                (proposal.height, proposal.round, proposal.content.clone()),
+                (qc.height, qc.round, qc.proposal.clone()),
+            );
+        }
+
+        if self.is_observer() || self.voted >= Some(proposal.round) {
+            return Ok(());
+        }
+        if !self.is_safe(&proposal) {
+            info!(
+                "The proposal of height {:?}, round {:?} conflicts with the lock",
+                proposal.height, proposal.round
+            );
+            return Ok(());
+        }
+
+        let vote = Vote {
+            vote_type: VoteType::Generic,
+            height: proposal.height,
+            round: proposal.round,
+            proposal: proposal.content,
+            voter: self.params.address.clone(),
+        };
+        debug!("Vote to {:?}", vote.proposal);
+        self.save_safety_state(Some(&vote))?;
+        self.voted = Some(vote.round);
+        self.send_bft_msg(CoreOutput::Vote(vote.clone()));
+        let weight = self.local_weight();
+        self.add_vote(vote, weight)
+    }
+
+    /// A function to save the lock, and the vote if any, before the vote is
+    /// sent. Only the last vote is kept as the node votes once in a view.
+    fn save_safety_state(&mut self, vote: Option<&Vote>) -> Result<(), BftError> {
+        let store = match self.safety_store.as_mut() {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let mut state = self.safety_state.clone();
+        if let Some(vote) = vote {
+            state.height = vote.height;
+            state.votes = vec![vote.clone()];
+        }
+        state.locked_qc = self.locked_qc.clone();
+        if let Err(e) = store.save(&state) {
+            error!("Save safety state error {:?}", e);
+            return Err(BftError::SafetyStoreErr);
+        }
+        self.safety_state = state;
+        Ok(())
+    }
+
+    fn is_safe(&self, proposal: &Proposal) -> bool {
+        let locked = match self.locked_qc.as_ref() {
+            Some(locked) if locked.height > self.commit_height => locked,
+            _ => return true,
+        };
+        let qc = match proposal.parent_qc.as_ref() {
+            Some(qc) => qc,
+            None => return false,
+        };
+        qc.round > locked.round || self.extends((qc.height, qc.round, qc.proposal.clone()), locked)
+    }
+
+    /// A function to check if the proposal is the locked one or a descendant
+    /// of it.
+    fn extends(&self, block: ViewBlock, locked: &QuorumCert) -> bool {
+        let mut block = block;
+        while block.0 > locked.height {
+            match self.parents.get(&block) {
+                Some(parent) => block = parent.clone(),
+                None => return false,
+            }
+        }
+        block == (locked.height, locked.round, locked.proposal.clone())
+    }
+
+    /// A function to handle a vote of the views from the current one to the
+    /// window ahead, at a height above the committed one.
+    fn handle_vote(&mut self, vote: Vote) -> Result<(), BftError> {
+        if vote.vote_type != VoteType::Generic
+            || vote.height <= self.commit_height
+            || vote.round < self.round
+            || vote.round - self.round > FUTURE_ROUND_WINDOW
+        {
+            return Ok(());
+        }
+        if vote.voter == self.params.address {
+            warn!("Receive a vote of the local node");
+            return Ok(());
+        }
+        let weight = match self.vote_weight_map.get(&vote.voter) {
+            Some(weight) => *weight,
+            None => {
+                error!("Lose vote weight of node {:?}", vote.voter);
+                return Ok(());
+            }
+        };
+        trace!(
+            "Receive a vote of height {:?}, round {:?}, to {:?}, from {:?}",
+            vote.height,
+            vote.round,
+            vote.proposal,
+            vote.voter
+        );
+        self.add_vote(vote, weight)
+    }
+
+    /// A function to add a vote, the proposal is certified once its votes
+    /// reach +2/3.
+    fn add_vote(&mut self, vote: Vote, weight: u64) -> Result<(), BftError> {
+        let (height, round, proposal) = (vote.height, vote.round, vote.proposal.clone());
+        let voteset = self
+            .votes
+            .entry((height, round))
+            .or_insert_with(VoteSet::new);
+        if !voteset.add(vote.voter, vote.proposal, weight) {
+            return Ok(());
+        }
+        let count = voteset
+            .votes_by_proposal
+            .get(&proposal)
+            .cloned()
+            .unwrap_or(0);
+        let certified = self.certs.contains_key(&(height, round, proposal.clone()));
+        if certified || !self.cal_above_threshold(count) {
+            return Ok(());
+        }
+
+        let votes =
+            self.votes[&(height, round)].extract_polc(height, round, VoteType::Generic, &proposal);
+        info!(
+            "Receive over 2/3 votes at height {:?}, round {:?}, to {:?}",
+            height, round, proposal
+        );
+        self.update_qc(QuorumCert {
+            height,
+            round,
+            proposal,
+            votes,
+        })
+    }
+
+    /// A function to update the chain with a new certificate. The parent of the
+    /// certified proposal is locked, and its grandparent is decided if the
+    /// three of them are of consecutive views. The node enters the next view
+    /// once the certificate is of the current view or a later one.
+    fn update_qc(&mut self, qc: QuorumCert) -> Result<(), BftError> {
+        let block = (qc.height, qc.round, qc.proposal.clone());
+        self.certs
+            .entry(block.clone())
+            .or_insert_with(|| qc.clone());
+        if self
+            .high_qc
+            .as_ref()
+            .map_or(true, |high| high.round < qc.round)
+        {
+            self.high_qc = Some(qc.clone());
+        }
+
+        if let Some(parent) = self.parents.get(&block).cloned() {
+            if let Some(parent_qc) = self.certs.get(&parent).cloned() {
+                if self
+                    .locked_qc
+                    .as_ref()
+                    .map_or(true, |locked| locked.round < parent_qc.round)
+                {
+                    debug!("Lock the proposal of height {:?}", parent_qc.height);
+                    self.locked_qc = Some(parent_qc);
+                    self.save_safety_state(None)?;
+                }
+            }
+            if let Some(grandparent) = self.parents.get(&parent).cloned() {
+                // a gap of views between them may hide a conflicting lock
+                if parent.1.checked_add(1) == Some(block.1)
+                    && grandparent.1.checked_add(1) == Some(parent.1)
+                {
+                    self.decide(grandparent);
+                    self.try_commit();
+                }
+            }
+        }
+
+        if qc.round >= self.round {
+            let next = qc.height.checked_add(1).ok_or(BftError::Overflow)?;
+            let next_round = qc.round.checked_add(1).ok_or(BftError::Overflow)?;
+            self.enter_view(next, next_round)?;
+        }
+        Ok(())
+    }
+
+    /// A function to decide the proposal and its undecided ancestors.
+    fn decide(&mut self, block: ViewBlock) {
+        let mut block = block;
+        while block.0 > self.commit_height {
+            if let Some(decided) = self.decided.get(&block.0) {
+                if decided.proposal != block.2 {
+                    error!("Decide two proposals at height {:?}", block.0);
+                }
+                return;
+            }
+            let cert = match self.certs.get(&block) {
+                Some(cert) => cert.clone(),
+                None => return,
+            };
+            self.decided.insert(block.0, cert);
+            match self.parents.get(&block) {
+                Some(parent) => block = parent.clone(),
+                None => return,
+            }
+        }
+    }
+
+    /// A function to commit the decided proposals in order. A proposal whose
+    /// content is not in the application is requested, and the commits after it
+    /// are held.
+    fn try_commit(&mut self) {
+        loop {
+            let next = self.commit_height.saturating_add(1);
+            let cert = match self.decided.get(&next) {
+                Some(cert) => cert.clone(),
+                None => break,
+            };
+            let id = (next, cert.proposal.clone());
+            if !self.contents.contains(&id) {
+                if self.requested_contents.insert(id) {
+                    info!(
+                        "Request the content of {:?} at height {:?}",
+                        cert.proposal, next
+                    );
+                    self.send_bft_msg(CoreOutput::RequestProposal {
+                        height: next,
+                        round: cert.round,
+                        digest: cert.proposal,
+                    });
+                }
+                break;
+            }
+            self.decided.remove(&next);
+            self.commit_height = next;
+            self.send_commit(cert);
+        }
+        self.prune();
+    }
+
+    fn send_commit(&mut self, cert: QuorumCert) {
+        let elapsed = self
+            .htimes
+            .get(&cert.height)
+            .map(|start| self.params.clock.now().saturating_duration_since(*start))
+            .unwrap_or_default();
+        info!(
+            "Commit {:?} at height {:?}, consensus time {:?}",
+            cert.proposal, cert.height, elapsed
+        );
+
+        let mut weight = 0u64;
+        let mut absent = Vec::new();
+        for address in self.authority_list.iter() {
+            let w = self.vote_weight_map.get(address).cloned().unwrap_or(0);
+            if cert.votes.iter().any(|vote| vote.voter == *address) {
+                weight = weight.saturating_add(w);
+            } else if w > 0 {
+                absent.push(address.clone());
+            }
+        }
+        let commit = Commit {
+            height: cert.height,
+            round: cert.round,
+            proposal: cert.proposal,
+            lock_votes: cert.votes.clone(),
+            address: self.params.address.clone(),
+            precommits: cert.votes,
+            absent,
+            precommit_weight: weight,
+            total_weight: u64::try_from(self.total_weight()).unwrap_or(u64::MAX),
+            rounds: cert.round.saturating_add(1),
+            elapsed: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+            fast_path: false,
+        };
+        self.send_bft_msg(CoreOutput::Commit(commit));
+    }
+
+    /// A function to drop the proposals of the committed heights, except the
+    /// last one, and the votes of the committed heights.
+    fn prune(&mut self) {
+        let height = self.commit_height;
+        self.parents.retain(|(h, _, _), _| *h >= height);
+        self.certs.retain(|(h, _, _), _| *h >= height);
+        self.contents.retain(|(h, _)| *h >= height);
+        self.votes.retain(|(h, _), _| *h > height);
+        self.requested_contents.retain(|(h, _)| *h > height);
+        self.htimes = self.htimes.split_off(&height);
+    }
+
+    /// A function to handle a round change to a later view, the node may wait
+    /// for the view at another height.
+    fn handle_round_change(&mut self, round_change: RoundChange) -> Result<(), BftError> {
+        if round_change.round <= self.round || round_change.voter == self.params.address {
+            return Ok(());
+        }
+        if round_change.round - self.round > FUTURE_ROUND_WINDOW {
+            warn!(
+                "Drop the round change to round {:?} from {:?}, self round is {:?}",
+                round_change.round, round_change.voter, self.round
+            );
+            return Ok(());
+        }
+        let weight = match self.vote_weight_map.get(&round_change.voter) {
+            Some(weight) => *weight,
+            None => {
+                error!("Lose vote weight of node {:?}", round_change.voter);
+                return Ok(());
+            }
+        };
+        self.add_round_change(round_change.round, round_change.voter, weight)
+    }
+
+    /// A function to add a round change of the local node or a received one,
+    /// the node enters the view once the round changes reach +2/3.
+    fn add_round_change(
+        &mut self,
+        round: u64,
+        voter: Address,
+        weight: u64,
+    ) -> Result<(), BftError> {
+        if let Some(sum) = self.round_changes.add(round, voter, weight) {
+            if self.cal_above_threshold(sum) {
+                info!("Receive over 2/3 round change, goto round {:?}", round);
+                self.enter_view(self.height, round)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A function to follow a status. The authorities are updated, and the
+    /// node restarts the chain after the status height in the current view if
+    /// it falls behind.
+    fn handle_status(&mut self, status: Status) -> Result<(), BftError> {
+        let new_height = status.height.checked_add(1).ok_or(BftError::Overflow)?;
+        self.authority_list = status.get_address_list();
+        self.propose_weight = status.get_propose_weight_list();
+        self.vote_weight_map = status.get_vote_weight_map();
+        if let Some(interval) = status.interval {
+            self.params.timer.set_total_duration(interval);
+        }
+
+        if status.height > self.commit_height {
+            self.commit_height = status.height;
+            self.decided = self.decided.split_off(&new_height);
+            self.try_commit();
+        }
+        if status.height >= self.height {
+            info!("Receive rich status, goto new height {:?}", new_height);
+            // the views only go up, and the lock above the status, such as the
+            // restored one, still binds the node
+            self.parents.clear();
+            self.certs.clear();
+            self.high_qc = None;
+            self.locked_qc = self.locked_qc.take().filter(|qc| qc.height > status.height);
+            self.decided.clear();
+            self.votes.clear();
+            self.htimes.clear();
+            self.enter_view(new_height, self.round)?;
+        }
+        Ok(())
+    }
+
+    /// A function to process a message. Any message from the network can not
+    /// panic the state machine, an invalid one leads to an error.
+    pub(crate) fn process(&mut self, msg: CoreInput) -> Result<(), BftError> {
+        match msg {
+            CoreInput::Proposal(proposal) => self.handle_proposal(proposal)?,
+            CoreInput::Vote(vote) => self.handle_vote(vote)?,
+            CoreInput::RoundChange(round_change) => self.handle_round_change(round_change)?,
+            CoreInput::Feed(feed) => {
+                if feed.height >= self.height {
+                    info!("Receive feed of height {:?}", feed.height);
+                    self.contents.insert((feed.height, feed.proposal.clone()));
+                    self.feed = Some(feed);
+                    self.try_propose()?;
+                }
+            }
+            CoreInput::ProposalContent(content) => {
+                self.contents.insert((content.height, content.digest));
+                self.try_commit();
+            }
+            CoreInput::Status(status) => self.handle_status(status)?,
+            _ => error!("Invalid Message!"),
+        }
+        Ok(())
+    }
+
+    /// A function to process a timeout. The node asks for the next round if the
+    /// view makes no progress.
+    pub(crate) fn timeout_process(&mut self, info: &TimeoutInfo) -> Result<(), BftError> {
+        if info.height != self.height
+            || info.round != self.round
+            || info.chain_id != DEFAULT_CHAIN_ID
+        {
+            return Ok(());
+        }
+        let round = self.round.checked_add(1).ok_or(BftError::Overflow)?;
+        if !self.is_observer() {
+            info!(
+                "Transmit round change at height {:?}, to round {:?}",
+                self.height, round
+            );
+            self.send_bft_msg(CoreOutput::RoundChange(RoundChange {
+                height: self.height,
+                round,
+                voter: self.params.address.clone(),
+            }));
+            let weight = self.local_weight();
+            self.add_round_change(round, self.params.address.clone(), weight)?;
+            if self.round == round {
+                // the local round change completes +2/3, the view timer is set
+                return Ok(());
+            }
+        }
+        // retransmit the round change until the view changes
+        self.set_view_timer();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Chained, FUTURE_ROUND_WINDOW};
+    use crate::{config::BftConfig, safety::FileSafetyStore, timer::TimerCmd, types::*, FromCore};
+    use crossbeam_channel::{unbounded, Receiver, Sender};
+    use std::collections::{BTreeMap, VecDeque};
+
+    struct Outbox(Sender<CoreOutput>);
+
+    impl FromCore for Outbox {
+        type Error = ();
+
+        fn send_msg(&mut self, msg: CoreOutput) -> Result<(), ()> {
+            self.0.send(msg).map_err(|_| ())
+        }
+    }
+
+    struct Node {
+        engine: Chained<Outbox>,
+        outbox: Receiver<CoreOutput>,
+        _timers: Receiver<TimerCmd>,
+    }
+
+    fn content(height: u64) -> Target {
+        Target::new(vec![height as u8, 0xcc])
+    }
+
+    fn authorities(count: usize) -> Status {
+        Status {
+            height: 0,
+            interval: None,
+            authority_list: (0..count)
+                .map(|i| crate::types::Node::new(Address::new(vec![i as u8])))
+                .collect(),
+        }
+    }
+
+    fn new_node(index: usize, status: Status) -> Node {
+        let (s, outbox) = unbounded();
+        let (ts, timers) = unbounded();
+        let mut engine = Chained::initialize(Outbox(s), ts, Address::new(vec![index as u8]));
+        engine.handle_input(CoreInput::Start);
+        engine.handle_input(CoreInput::Status(status));
+        Node {
+            engine,
+            outbox,
+            _timers: timers,
+        }
+    }
+
+    fn start(count: usize, report_evidence: bool) -> Vec<Node> {
+        (0..count)
+            .map(|i| {
+                let mut node = new_node(i, authorities(count));
+                node.engine.params.report_evidence = report_evidence;
+                node
+            })
+            .collect()
+    }
+
+    /// Fire the view timer of the node.
+    fn timeout(node: &mut Node) {
+        let info = crate::timer::TimeoutInfo {
+            timeval: node.engine.params.clock.now(),
+            chain_id: 0,
+            height: node.engine.height,
+            round: node.engine.round,
+            step: crate::algorithm::Step::ProposeWait,
+        };
+        node.engine.handle_timeout(&info);
+    }
+
+    /// Deliver the messages of the live nodes until the network is quiet, the
+    /// proposals are fed up to the max height and the commits of each node are
+    /// collected.
+    fn run(
+        nodes: &mut [Node],
+        crashed: Option<usize>,
+        max_height: u64,
+        commits: &mut [Vec<Commit>],
+    ) {
+        let mut queue = VecDeque::new();
+        loop {
+            for (i, node) in nodes.iter().enumerate() {
+                for msg in node.outbox.try_iter() {
+                    queue.push_back((i, msg));
+                }
+            }
+            let (from, msg) = match queue.pop_front() {
+                Some(event) => event,
+                None => return,
+            };
+            if Some(from) == crashed {
+                continue;
+            }
+            let broadcast = match msg {
+                CoreOutput::Proposal(p) => CoreInput::Proposal(p),
+                CoreOutput::Vote(v) => CoreInput::Vote(v),
+                CoreOutput::RoundChange(r) => CoreInput::RoundChange(r),
+                CoreOutput::GetProposalRequest(height) | CoreOutput::PrepareProposal { height } => {
+                    if height > max_height {
+                        continue;
+                    }
+                    let feed = Feed {
+                        height,
+                        proposal: content(height),
+                    };
+                    nodes[from].engine.handle_input(CoreInput::Feed(feed));
+                    continue;
+                }
+                CoreOutput::Commit(commit) => {
+                    let status = Status {
+                        height: commit.height,
+                        ..authorities(nodes.len())
+                    };
+                    commits[from].push(commit);
+                    nodes[from].engine.handle_input(CoreInput::Status(status));
+                    continue;
+                }
+                _ => continue,
+            };
+            for (to, node) in nodes.iter_mut().enumerate() {
+                if to != from && Some(to) != crashed {
+                    node.engine.handle_input(broadcast.clone());
+                }
+            }
+        }
+    }
+
+    fn check_commits(commits: &[Vec<Commit>], crashed: Option<usize>, height: u64) {
+        let mut decided = BTreeMap::new();
+        for (i, node_commits) in commits.iter().enumerate() {
+            if Some(i) == crashed {
+                continue;
+            }
+            for (commit, h) in node_commits.iter().zip(1..) {
+                assert_eq!(commit.height, h);
+                assert_eq!(commit.proposal, content(h));
+                assert!(commit.precommit_weight * 3 > commit.total_weight * 2);
+                let proposal = decided.entry(h).or_insert_with(|| commit.proposal.clone());
+                assert_eq!(*proposal, commit.proposal);
+            }
+            assert!(node_commits.len() as u64 >= height);
+        }
+    }
+
+    #[test]
+    fn test_chained_commits() {
+        let mut nodes = start(4, false);
+        let mut commits = vec![Vec::new(); 4];
+        run(&mut nodes, None, 7, &mut commits);
+        // the certificate of height 7 commits height 5, and the pipeline waits
+        // for the proposal of height 8
+        for (node, node_commits) in nodes.iter().zip(commits.iter()) {
+            assert_eq!(node.engine.height, 8);
+            assert_eq!(node_commits.len(), 5);
+        }
+        check_commits(&commits, None, 5);
+    }
+
+    #[test]
+    fn test_chained_view_change() {
+        let mut nodes = start(4, false);
+        let crashed = nodes[0]
+            .engine
+            .proposer_of(1, 0)
+            .and_then(|address| (0..4).find(|i| Address::new(vec![*i as u8]) == *address));
+        let mut commits = vec![Vec::new(); 4];
+        for _ in 0..20 {
+            run(&mut nodes, crashed, 5, &mut commits);
+            if commits[(crashed.unwrap() + 1) % 4].len() >= 3 {
+                break;
+            }
+            // fire the view timers of the live nodes
+            for (i, node) in nodes.iter_mut().enumerate() {
+                if Some(i) != crashed {
+                    timeout(node);
+                }
+            }
+        }
+        assert!(nodes[crashed.unwrap()].engine.height == 1);
+        check_commits(&commits, crashed, 3);
+    }
+
+    #[test]
+    fn test_chained_safety_store() {
+        let path = ::std::env::temp_dir().join(format!("bft-chained-{}", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let mut nodes = start(4, false);
+        let mut config = BftConfig::new();
+        config
+            .set_safety_store(FileSafetyStore::new(&path))
+            .unwrap();
+        nodes[0].engine.apply_config(config);
+        let mut commits = vec![Vec::new(); 4];
+        run(&mut nodes, None, 4, &mut commits);
+        assert!(nodes[0].engine.voted.is_some());
+        assert!(nodes[0].engine.locked_qc.is_some());
+
+        // the restarted node keeps the last vote and the lock above the status
+        let (s, _outbox) = unbounded();
+        let (ts, _timers) = unbounded();
+        let mut engine = Chained::initialize(Outbox(s), ts, Address::new(vec![0]));
+        let mut config = BftConfig::new();
+        config
+            .set_safety_store(FileSafetyStore::new(&path))
+            .unwrap();
+        engine.apply_config(config);
+        engine.handle_input(CoreInput::Start);
+        engine.handle_input(CoreInput::Status(authorities(4)));
+        assert_eq!(engine.voted, nodes[0].engine.voted);
+        assert_eq!(engine.locked_qc, nodes[0].engine.locked_qc);
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_chained_invalid_cert() {
+        let mut nodes = start(4, true);
+        let proposer = nodes[0].engine.proposer_of(2, 0).cloned().unwrap();
+        let vote = Vote {
+            vote_type: VoteType::Generic,
+            height: 1,
+            round: 0,
+            proposal: content(1),
+            voter: proposer.clone(),
+        };
+        let proposal = Proposal {
+            height: 2,
+            round: 0,
+            content: content(2),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer,
+            parent_qc: Some(QuorumCert {
+                height: 1,
+                round: 0,
+                proposal: content(1),
+                votes: vec![vote],
+            }),
+        };
+        let node = nodes
+            .iter_mut()
+            .find(|node| node.engine.params.address != proposal.proposer);
+        let node = node.unwrap();
+        node.outbox.try_iter().for_each(drop);
+        node.engine.handle_input(CoreInput::Proposal(proposal));
+
+        let outputs: Vec<CoreOutput> = node.outbox.try_iter().collect();
+        assert!(outputs.iter().any(|msg| matches!(
+            msg,
+            CoreOutput::Evidence(e) if e.reason == RejectReason::InvalidQuorumCert
+        )));
+        assert!(!outputs.iter().any(|msg| matches!(msg, CoreOutput::Vote(_))));
+        assert_eq!(node.engine.height, 1);
+    }
+
+    /// Start the honest nodes 0, 1 and 2 of 4 nodes, the byzantine node 3 is
+    /// the only proposer and the messages are delivered by hand.
+    fn start_byzantine_proposer() -> Vec<Node> {
+        let mut status = authorities(4);
+        for node in status.authority_list.iter_mut() {
+            let weight = if node.address == Address::new(vec![3]) {
+                1
+            } else {
+                0
+            };
+            node.set_propose_weight(weight);
+        }
+        (0..3).map(|i| new_node(i, status.clone())).collect()
+    }
+
+    fn propose(height: u64, round: u64, tag: u8, parent_qc: Option<QuorumCert>) -> CoreInput {
+        CoreInput::Proposal(Proposal {
+            height,
+            round,
+            content: Target::new(vec![tag]),
+            lock_round: None,
+            lock_votes: Vec::new(),
+            proposer: Address::new(vec![3]),
+            parent_qc,
+        })
+    }
+
+    /// Deliver the message to the nodes, and collect their outputs.
+    fn deliver(nodes: &mut [Node], to: &[usize], msg: CoreInput) -> Vec<CoreOutput> {
+        let mut outputs = Vec::new();
+        for i in to.iter() {
+            nodes[*i].engine.handle_input(msg.clone());
+            outputs.extend(nodes[*i].outbox.try_iter());
+        }
+        outputs
+    }
+
+    /// Make the certificate of the votes of the three honest nodes.
+    fn cert(outputs: Vec<CoreOutput>) -> QuorumCert {
+        let votes: Vec<Vote> = outputs
+            .into_iter()
+            .filter_map(|msg| match msg {
+                CoreOutput::Vote(vote) => Some(vote),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(votes.len(), 3);
+        QuorumCert {
+            height: votes[0].height,
+            round: votes[0].round,
+            proposal: votes[0].proposal.clone(),
+            votes,
+        }
+    }
+
+    /// Time out the nodes, and deliver their round changes and the extra ones
+    /// to each other.
+    fn change_view(nodes: &mut [Node], to: &[usize], extra: Vec<RoundChange>) {
+        let mut round_changes = extra;
+        for i in to.iter() {
+            timeout(&mut nodes[*i]);
+            round_changes.extend(nodes[*i].outbox.try_iter().filter_map(|msg| match msg {
+                CoreOutput::RoundChange(rc) => Some(rc),
+                _ => None,
+            }));
+        }
+        for rc in round_changes {
+            for i in to.iter() {
+                nodes[*i]
+                    .engine
+                    .handle_input(CoreInput::RoundChange(rc.clone()));
+            }
+        }
+    }
+
+    fn commits(outputs: &[CoreOutput]) -> Vec<(u64, Target)> {
+        outputs
+            .iter()
+            .filter_map(|msg| match msg {
+                CoreOutput::Commit(commit) => Some((commit.height, commit.proposal.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chained_stale_cert() {
+        let mut nodes = start_byzantine_proposer();
+
+        // the byzantine node gets the certificates of b1 and b2 in views 0 and
+        // 1, and keeps them to itself
+        let qc_b1 = cert(deliver(&mut nodes, &[0, 1, 2], propose(1, 0, 0xb1, None)));
+        let qc_b2 = cert(deliver(
+            &mut nodes,
+            &[0, 1, 2],
+            propose(2, 1, 0xb2, Some(qc_b1)),
+        ));
+        change_view(&mut nodes, &[0, 1, 2], Vec::new());
+
+        // the honest nodes certify c1 and c2 in views 2 and 3, and node 0 alone
+        // sees the certificate of c3 in view 4, which commits c1
+        let qc_c1 = cert(deliver(&mut nodes, &[0, 1, 2], propose(1, 2, 0xc1, None)));
+        let qc_c2 = cert(deliver(
+            &mut nodes,
+            &[0, 1, 2],
+            propose(2, 3, 0xc2, Some(qc_c1.clone())),
+        ));
+        let qc_c3 = cert(deliver(
+            &mut nodes,
+            &[0, 1, 2],
+            propose(3, 4, 0xc3, Some(qc_c2)),
+        ));
+        let outputs = deliver(&mut nodes, &[0], propose(4, 5, 0xc4, Some(qc_c3)));
+        assert_eq!(commits(&outputs), vec![(1, qc_c1.proposal.clone())]);
+        assert_eq!(nodes[1].engine.locked_qc, Some(qc_c1));
+
+        // the certificate of b2 is of a view before the lock of nodes 1 and 2,
+        // so they do not vote on its child, and b1 is never committed
+        let byzantine = RoundChange {
+            height: 3,
+            round: 5,
+            voter: Address::new(vec![3]),
+        };
+        change_view(&mut nodes, &[1, 2], vec![byzantine]);
+        assert_eq!(nodes[1].engine.round, 5);
+        let outputs = deliver(&mut nodes, &[1, 2], propose(3, 5, 0xd, Some(qc_b2)));
+        assert!(!outputs
+            .iter()
+            .any(|msg| matches!(msg, CoreOutput::Vote(_) | CoreOutput::Commit(_))));
+    }
+
+    #[test]
+    fn test_chained_view_gap() {
+        let mut nodes = start_byzantine_proposer();
+        let qc_b1 = cert(deliver(&mut nodes, &[0, 1, 2], propose(1, 0, 0xb1, None)));
+        let qc_b2 = cert(deliver(
+            &mut nodes,
+            &[0, 1, 2],
+            propose(2, 1, 0xb2, Some(qc_b1.clone())),
+        ));
+        change_view(&mut nodes, &[0, 1, 2], Vec::new());
+        change_view(&mut nodes, &[0, 1, 2], Vec::new());
+        assert_eq!(nodes[0].engine.round, 3);
+
+        // b1, b2 and b3 of views 0, 1 and 3 are not a three-chain
+        let qc_b3 = cert(deliver(
+            &mut nodes,
+            &[0, 1, 2],
+            propose(3, 3, 0xb3, Some(qc_b2.clone())),
+        ));
+        let qc_b4 = cert(deliver(
+            &mut nodes,
+            &[0, 1, 2],
+            propose(4, 4, 0xb4, Some(qc_b3.clone())),
+        ));
+        let outputs = deliver(&mut nodes, &[0, 1, 2], propose(5, 5, 0xb5, Some(qc_b4)));
+        assert!(commits(&outputs).is_empty());
+        assert_eq!(nodes[0].engine.commit_height, 0);
+
+        // b3, b4 and b5 of views 3, 4 and 5 commit b3 and its ancestors
+        let qc_b5 = cert(outputs);
+        let outputs = deliver(&mut nodes, &[0], propose(6, 6, 0xb6, Some(qc_b5)));
+        assert_eq!(
+            commits(&outputs),
+            vec![
+                (1, qc_b1.proposal),
+                (2, qc_b2.proposal),
+                (3, qc_b3.proposal)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chained_local_round_change_quorum() {
+        let mut nodes = start(4, false);
+        for (from, to) in [(1, 0), (2, 0)].iter() {
+            timeout(&mut nodes[*from]);
+            let msgs: Vec<CoreOutput> = nodes[*from].outbox.try_iter().collect();
+            for msg in msgs {
+                if let CoreOutput::RoundChange(rc) = msg {
+                    nodes[*to].engine.handle_input(CoreInput::RoundChange(rc));
+                }
+            }
+        }
+        assert_eq!(nodes[0].engine.round, 0);
+
+        // the round change of the local node completes +2/3 on its timeout
+        timeout(&mut nodes[0]);
+        assert_eq!(nodes[0].engine.round, 1);
+        assert_eq!(nodes[0].engine.height, 1);
+    }
+
+    #[test]
+    fn test_chained_vote_window() {
+        let mut nodes = start(4, false);
+        let voter = Address::new(vec![1]);
+        for height in 1..4 {
+            for round in (0..FUTURE_ROUND_WINDOW * 4).chain(vec![u64::MAX]) {
+                let vote = Vote {
+                    vote_type: VoteType::Generic,
+                    height,
+                    round,
+                    proposal: Target::new(vec![0xee]),
+                    voter: voter.clone(),
+                };
+                nodes[0].engine.handle_input(CoreInput::Vote(vote));
+                let round_change = RoundChange {
+                    height,
+                    round,
+                    voter: voter.clone(),
+                };
+                nodes[0]
+                    .engine
+                    .handle_input(CoreInput::RoundChange(round_change));
+            }
+        }
+        let window = FUTURE_ROUND_WINDOW as usize;
+        assert_eq!(nodes[0].engine.votes.len(), 3 * (window + 1));
+        assert_eq!(nodes[0].engine.round_changes.senders.len(), window);
+
+        // the votes of the committed heights and the past views are dropped
+        let mut commits = vec![Vec::new(); 4];
+        run(&mut nodes, None, 7, &mut commits);
+        for node in nodes.iter() {
+            let engine = &node.engine;
+            assert!(engine.commit_height >= 5);
+            assert!(engine
+                .votes
+                .keys()
+                .all(|(h, r)| *h > engine.commit_height && *r >= engine.round));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crossbeam_channel::{after, bounded, Receiver, Sender};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A source of time for the timers and the message filters of BFT core.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Return the current instant of the clock.
+    fn now(&self) -> Instant;
+    /// Return a receiver that receives the current instant once the clock
+    /// reaches the deadline.
+    fn after(&self, deadline: Instant) -> Receiver<Instant>;
+}
+
+/// The clock of the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn after(&self, deadline: Instant) -> Receiver<Instant> {
+        after(deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// A clock that is advanced manually, it runs BFT core in virtual time. The
+/// clones of a mock clock share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    inner: Arc<Mutex<MockInner>>,
+}
+
+#[derive(Debug)]
+struct MockInner {
+    now: Instant,
+    waiters: Vec<(Instant, Sender<Instant>)>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new(Instant::now())
+    }
+}
+
+impl MockClock {
+    /// A function to create a new mock clock starting from the instant.
+    pub fn new(start: Instant) -> Self {
+        MockClock {
+            inner: Arc::new(Mutex::new(MockInner {
+                now: start,
+                waiters: Vec::new(),
+            })),
+        }
+    }
+
+    /// A function to advance the clock by the duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        let now = inner.now + duration;
+        inner.now = now;
+        inner.waiters.retain(|(deadline, waiter)| {
+            if *deadline > now {
+                return true;
+            }
+            let _ = waiter.send(now);
+            false
+        });
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.inner.lock().unwrap().now
+    }
+
+    fn after(&self, deadline: Instant) -> Receiver<Instant> {
+        let (s, r) = bounded(1);
+        let mut inner = self.inner.lock().unwrap();
+        if deadline <= inner.now {
+            let _ = s.send(inner.now);
+        } else {
+            inner.waiters.push((deadline, s));
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let start = Instant::now();
+        let clock = MockClock::new(start);
+        let shared = clock.clone();
+        let ms = Duration::from_millis;
+
+        let done = clock.after(start);
+        let first = clock.after(start + ms(10));
+        let second = clock.after(start + ms(30));
+        assert_eq!(done.try_recv(), Ok(start));
+        assert!(first.try_recv().is_err());
+
+        shared.advance(ms(20));
+        assert_eq!(clock.now(), start + ms(20));
+        assert_eq!(first.try_recv(), Ok(start + ms(20)));
+        assert!(second.try_recv().is_err());
+
+        shared.advance(ms(10));
+        assert_eq!(second.try_recv(), Ok(start + ms(30)));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    clock::{Clock, SystemClock},
+    error::BftError,
+    safety::{SafetyState, SafetyStore},
+    types::Target,
+};
+
+use log::error;
+use std::{sync::Arc, time::Duration};
+
+/// The consensus engine of a BFT core.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EngineMode {
+    /// Run one height at a time, the next height starts after its `Status`.
+    #[default]
+    Classic,
+    /// Overlap the phases of consecutive heights in the chained HotStuff
+    /// style. The votes on a proposal certify its parent, a proposal is
+    /// committed once it has a chain of three certified descendants.
+    Chained,
+}
+
+/// The policy of a proposer without a `Feed` of the height.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EmptyBlockPolicy {
+    /// Wait for a feed until the propose timer, which doubles every round.
+    #[default]
+    Wait,
+    /// Wait for a feed at most the duration.
+    WaitFor(Duration),
+    /// Propose the empty target supplied by the application at once, if
+    /// `FromCore::allow_empty_block` allows an empty block at the height.
+    /// Otherwise wait for a feed.
+    Propose(Target),
+    /// Skip proposing and prevote nil at once, so that the round fails fast.
+    /// The round change of the proposer makes the other nodes waiting for its
+    /// proposal prevote nil at once too.
+    Skip,
+}
+
+/// The config of a BFT core.
+#[derive(Debug)]
+pub struct BftConfig {
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) safety: Option<(Box<dyn SafetyStore>, SafetyState)>,
+    pub(crate) report_evidence: bool,
+    pub(crate) authority_window: u64,
+    pub(crate) empty_block: EmptyBlockPolicy,
+    pub(crate) mode: EngineMode,
+    pub(crate) fast_commit: Option<(u64, u64)>,
+}
+
+impl Default for BftConfig {
+    fn default() -> Self {
+        BftConfig {
+            clock: Arc::new(SystemClock),
+            safety: None,
+            report_evidence: true,
+            authority_window: 1,
+            empty_block: EmptyBlockPolicy::default(),
+            mode: EngineMode::default(),
+            fast_commit: None,
+        }
+    }
+}
+
+impl BftConfig {
+    /// A function to create a new config with the system clock and without a
+    /// safety store, the rejected proposals are reported as evidence and the
+    /// authorities of the last height are kept. A proposer without a feed
+    /// waits for it, and the classic engine runs.
+    pub fn new() -> Self {
+        BftConfig::default()
+    }
+
+    /// A function to set the clock of the timers and the filters.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// A function to set whether a rejected proposal is reported as
+    /// `CoreOutput::Evidence`.
+    pub fn set_report_evidence(&mut self, report: bool) {
+        self.report_evidence = report;
+    }
+
+    /// A function to set the number of the past heights whose authorities are
+    /// kept. The messages of a past height are checked against the authorities
+    /// of that height, the ones of a height out of the window are dropped.
+    pub fn set_authority_window(&mut self, heights: u64) {
+        self.authority_window = heights;
+    }
+
+    /// A function to set the policy of a proposer without a feed.
+    pub fn set_empty_block_policy(&mut self, policy: EmptyBlockPolicy) {
+        self.empty_block = policy;
+    }
+
+    /// A function to set the threshold of the fast path as a fraction of the
+    /// vote weight, `(numerator, denominator)`. If the prevotes of round 0 on a
+    /// proposal reach the threshold, the node commits at once without
+    /// precommits. `None` turns the fast path off, which is the default. The
+    /// fast path binds the prevotes of round 0, so all the authorities must set
+    /// the same threshold. A node stays bound unless the other prevotes of round
+    /// 0 are above 4/3 minus the threshold, so a threshold below 1 lets an
+    /// equivocating proposer bind the nodes to different proposals for good.
+    /// Only a threshold of all the vote weight, such as `Some((1, 1))`, is
+    /// allowed, other thresholds are an error.
+    pub fn set_fast_commit(&mut self, threshold: Option<(u64, u64)>) -> Result<(), BftError> {
+        if let Some((numerator, denominator)) = threshold {
+            if denominator == 0 || numerator != denominator {
+                error!(
+                    "Invalid fast path threshold {:?}/{:?}",
+                    numerator, denominator
+                );
+                return Err(BftError::InvalidThreshold);
+            }
+        }
+        self.fast_commit = threshold;
+        Ok(())
+    }
+
+    /// A function to set the consensus engine. The empty block policy, the
+    /// authority window and the fast path apply to the classic engine only, the
+    /// chained engine saves its last vote and lock in the safety store.
+    pub fn set_engine_mode(&mut self, mode: EngineMode) {
+        self.mode = mode;
+    }
+
+    /// A function to set the safety store. The saved state is loaded at once, an
+    /// unreadable store is an error as the node cannot tell what it signed.
+    pub fn set_safety_store<S: SafetyStore + 'static>(
+        &mut self,
+        mut store: S,
+    ) -> Result<(), BftError> {
+        let state = store.load().map_err(|e| {
+            error!("Load safety store {:?} error {:?}", store, e);
+            BftError::SafetyStoreErr
+        })?;
+        self.safety = Some((Box::new(store), state.unwrap_or_default()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fast_commit_threshold() {
+        let mut config = BftConfig::new();
+        for threshold in [(1, 0), (0, 0), (7, 6), (2, 3), (5, 6), (0, 1)].iter() {
+            assert_eq!(
+                config.set_fast_commit(Some(*threshold)),
+                Err(BftError::InvalidThreshold)
+            );
+            assert_eq!(config.fast_commit, None);
+        }
+        for threshold in [(1, 1), (6, 6), (u64::MAX, u64::MAX)].iter() {
+            assert_eq!(config.set_fast_commit(Some(*threshold)), Ok(()));
+            assert_eq!(config.fast_commit, Some(*threshold));
+        }
+        assert_eq!(config.set_fast_commit(None), Ok(()));
+        assert_eq!(config.fast_commit, None);
+    }
+}
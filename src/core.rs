@@ -1,11 +1,17 @@
 use crate::{
     algorithm::{Bft, INIT_HEIGHT},
+    chained::Chained,
+    clock::Clock,
+    config::{BftConfig, EngineMode},
     error::BftError,
+    transport::{Router, Transport, INBOUND_QUEUE_LEN},
     types::*,
     FromCore,
 };
 
-use crossbeam_channel::{unbounded, Sender};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use log::debug;
+use std::{sync::Arc, thread};
 
 /// Result of Bft Core.
 pub type Result<T> = ::std::result::Result<T, BftError>;
@@ -20,14 +26,84 @@ pub struct Core {
 impl Core {
     /// A function to create a new Bft Core.
     pub fn new<T: FromCore + Send + 'static>(s: T, address: Address) -> Self {
+        Core::with_config(s, address, BftConfig::new())
+    }
+
+    /// A function to create a new Bft Core whose timers and filters run on the
+    /// clock, a `MockClock` runs the core in virtual time.
+    pub fn with_clock<T: FromCore + Send + 'static>(
+        s: T,
+        address: Address,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let mut config = BftConfig::new();
+        config.set_clock(clock);
+        Core::with_config(s, address, config)
+    }
+
+    /// A function to create a new Bft Core of the config.
+    pub fn with_config<T: FromCore + Send + 'static>(
+        s: T,
+        address: Address,
+        config: BftConfig,
+    ) -> Self {
         let (sender, internal_receiver) = unbounded();
-        Bft::start(s, internal_receiver, address);
+        Core::start(s, internal_receiver, address, config);
+        Core {
+            sender,
+            height: INIT_HEIGHT,
+        }
+    }
+
+    /// A function to create a new Bft Core that exchanges consensus messages
+    /// through the transport. Proposals and votes are sent to the peers and
+    /// other messages are sent to the `FromCore` sink. The messages from the
+    /// transport are input as they are, so the transport must check their
+    /// signatures. They are dropped while the core falls behind.
+    pub fn with_transport<T, N>(s: T, transport: N, address: Address, config: BftConfig) -> Self
+    where
+        T: FromCore + Send + 'static,
+        N: Transport + Send + 'static,
+    {
+        let (sender, internal_receiver) = unbounded();
+        let incoming = transport.incoming();
+        let peer_sender = sender.clone();
+        thread::spawn(move || {
+            for msg in incoming.iter() {
+                if peer_sender.len() >= INBOUND_QUEUE_LEN {
+                    debug!("The core falls behind, drop the message from peers");
+                    continue;
+                }
+                if peer_sender.send(msg.into()).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Core::start(
+            Router::new(s, transport),
+            internal_receiver,
+            address,
+            config,
+        );
         Core {
             sender,
             height: INIT_HEIGHT,
         }
     }
 
+    fn start<T: FromCore + Send + 'static>(
+        s: T,
+        receiver: Receiver<CoreInput>,
+        address: Address,
+        config: BftConfig,
+    ) {
+        match config.mode {
+            EngineMode::Classic => Bft::start(s, receiver, address, config),
+            EngineMode::Chained => Chained::start(s, receiver, address, config),
+        }
+    }
+
     /// A function to send BFT message to BFT core.
     pub fn send_bft_msg(&mut self, msg: CoreInput) -> Result<()> {
         match msg {
@@ -35,7 +111,7 @@ impl Core {
                 let status_height = s.height;
                 if self.sender.send(CoreInput::Status(s)).is_ok() {
                     if self.height <= status_height {
-                        self.height = status_height + 1;
+                        self.height = status_height.saturating_add(1);
                     }
                     Ok(())
                 } else {
@@ -55,8 +131,9 @@ impl Core {
 #[cfg(test)]
 mod test {
     use super::Core as Bft;
-    use crate::{types::*, FromCore};
-    use crossbeam_channel::{unbounded, Sender};
+    use crate::{clock::MockClock, types::*, FromCore};
+    use crossbeam_channel::{unbounded, Receiver, Sender};
+    use std::{sync::Arc, time::Duration};
 
     #[derive(Debug)]
     enum Error {
@@ -103,4 +180,73 @@ mod test {
             }
         }
     }
+
+    /// Advance the clock a second at a time until the core sends a message that
+    /// matches, the timers of the core fire in virtual time.
+    fn advance_until<F>(clock: &MockClock, r: &Receiver<CoreOutput>, matches: F) -> CoreOutput
+    where
+        F: Fn(&CoreOutput) -> bool,
+    {
+        for _ in 0..100 {
+            clock.advance(Duration::from_secs(1));
+            while let Ok(msg) = r.recv_timeout(Duration::from_millis(50)) {
+                if matches(&msg) {
+                    return msg;
+                }
+            }
+        }
+        panic!("The core sends no matched message");
+    }
+
+    #[test]
+    fn test_mock_clock_round_change() {
+        let (s, r) = unbounded();
+        let clock = MockClock::default();
+        let local = Address::new(vec![0]);
+        let mut bft = Bft::with_clock(SendMsg(s), local.clone(), Arc::new(clock.clone()));
+        let authority_list: Vec<Node> =
+            (0..4u8).map(|i| Node::new(Address::new(vec![i]))).collect();
+        bft.send_bft_msg(CoreInput::Start).unwrap();
+        bft.send_bft_msg(CoreInput::Status(Status {
+            height: 0,
+            interval: None,
+            authority_list,
+        }))
+        .unwrap();
+
+        // nothing is proposed, the propose timer of round 0 times out
+        let msg = advance_until(&clock, &r, |msg| matches!(msg, CoreOutput::RoundChange(_)));
+        assert_eq!(
+            msg,
+            CoreOutput::RoundChange(RoundChange {
+                height: 1,
+                round: 1,
+                voter: local.clone(),
+            })
+        );
+
+        // the round changes of two more nodes reach +2/3
+        for i in 1..3u8 {
+            bft.send_bft_msg(CoreInput::RoundChange(RoundChange {
+                height: 1,
+                round: 1,
+                voter: Address::new(vec![i]),
+            }))
+            .unwrap();
+        }
+        let msg = advance_until(&clock, &r, |msg| match msg {
+            CoreOutput::Vote(vote) => vote.round == 1,
+            _ => false,
+        });
+        assert_eq!(
+            msg,
+            CoreOutput::Vote(Vote {
+                vote_type: VoteType::Prevote,
+                height: 1,
+                round: 1,
+                proposal: Target::new(Vec::new()),
+                voter: local,
+            })
+        );
+    }
 }
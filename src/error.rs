@@ -7,6 +7,25 @@ pub enum BftError {
     RecvMsgErr,
     /// Message type error.
     MsgTypeErr,
+    /// The local node is not in the authority list.
+    NotAuthority,
+    /// The authority list is invalid, the weight sum is zero or overflows.
+    InvalidAuthority,
+    /// The height or the round overflows.
+    Overflow,
+    /// The chain is not in the core group.
+    UnknownChain,
+    /// The chain is already in the core group.
+    ChainExists,
+    /// Read or write the safety store error.
+    SafetyStoreErr,
+    /// The vote conflicts with a vote signed before.
+    ConflictVote,
+    /// The aggregate proof is invalid, such as a wrong signature, a voter out
+    /// of the authority list or a vote weight below 2/3.
+    InvalidProof,
+    /// The fast path threshold is not all the vote weight.
+    InvalidThreshold,
     /// Unreachable error.
     Unreachable,
 }
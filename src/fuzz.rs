@@ -0,0 +1,86 @@
+use crate::{
+    algorithm::Bft,
+    error::BftError,
+    timer::{TimeoutInfo, TimerCmd, TimerId},
+    types::*,
+    FromCore,
+};
+
+use crossbeam_channel::{unbounded, Receiver};
+use serde_derive::{Deserialize, Serialize};
+
+/// An input of the fuzzing engine.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum FuzzInput {
+    /// A message to process.
+    Msg(CoreInput),
+    /// Fire the pending timer of the index, modulo the count of pending timers.
+    Timeout(u16),
+}
+
+/// A sink that drops all output of the engine.
+struct Discard;
+
+impl FromCore for Discard {
+    type Error = ();
+
+    fn send_msg(&mut self, _msg: CoreOutput) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+/// A deterministic BFT state machine without threads. Timers are never fired by
+/// the clock, they are kept pending until an input fires one of them.
+pub struct FuzzEngine {
+    bft: Bft<Discard>,
+    timers: Receiver<TimerCmd>,
+    pending: Vec<(TimerId, TimeoutInfo)>,
+}
+
+impl FuzzEngine {
+    /// A function to create a new fuzzing engine of the local address.
+    pub fn new(address: Address) -> Self {
+        let (ts, timers) = unbounded();
+        FuzzEngine {
+            bft: Bft::initialize(Discard, ts, address),
+            timers,
+            pending: Vec::new(),
+        }
+    }
+
+    /// A function to feed an input to the engine.
+    pub fn feed(&mut self, input: FuzzInput) -> Result<(), BftError> {
+        let res = match input {
+            FuzzInput::Msg(msg) => self.bft.process(msg),
+            FuzzInput::Timeout(index) => {
+                if self.pending.is_empty() {
+                    return Ok(());
+                }
+                let (_, info) = self.pending.remove(usize::from(index) % self.pending.len());
+                self.bft.timeout_process(&info)
+            }
+        };
+        for cmd in self.timers.try_iter() {
+            match cmd {
+                TimerCmd::Set(id, info) => self.pending.push((id, info)),
+                cmd => self.pending.retain(|(id, info)| !cmd.cancels(id, info)),
+            }
+        }
+        res
+    }
+}
+
+/// A function to decode the data as a bincode serialized sequence of inputs and
+/// feed them to a new engine. The local address is `[0]`. Errors are expected,
+/// any panic is a bug.
+pub fn run(data: &[u8]) {
+    let inputs: Vec<FuzzInput> = match bincode::deserialize(data) {
+        Ok(inputs) => inputs,
+        Err(_) => return,
+    };
+
+    let mut engine = FuzzEngine::new(Address::new(vec![0]));
+    for input in inputs.into_iter() {
+        let _ = engine.feed(input);
+    }
+}
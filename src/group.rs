@@ -0,0 +1,419 @@
+use crate::{
+    algorithm::{Bft, INIT_HEIGHT},
+    clock::{Clock, SystemClock},
+    config::BftConfig,
+    core::Result,
+    error::BftError,
+    timer::{TimeoutInfo, TimerCmd, WaitTimer},
+    types::*,
+    FromCore, FromGroup,
+};
+
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
+use std::{collections::HashMap, sync::Arc, thread};
+
+/// A `FromCore` sink whose error is formatted as a string.
+struct StringSink<T>(T);
+
+impl<T: FromCore> FromCore for StringSink<T> {
+    type Error = String;
+
+    fn send_msg(&mut self, msg: CoreOutput) -> ::std::result::Result<(), String> {
+        self.0.send_msg(msg).map_err(|e| format!("{:?}", e))
+    }
+
+    fn allow_empty_block(&mut self, height: u64) -> bool {
+        self.0.allow_empty_block(height)
+    }
+}
+
+/// A `FromCore` sink of any type, the error is formatted as a string.
+struct AnySink(Box<dyn FromCore<Error = String> + Send>);
+
+impl AnySink {
+    fn new<T: FromCore + Send + 'static>(s: T) -> Self {
+        AnySink(Box::new(StringSink(s)))
+    }
+}
+
+impl FromCore for AnySink {
+    type Error = String;
+
+    fn send_msg(&mut self, msg: CoreOutput) -> ::std::result::Result<(), String> {
+        self.0.send_msg(msg)
+    }
+
+    fn allow_empty_block(&mut self, height: u64) -> bool {
+        self.0.allow_empty_block(height)
+    }
+}
+
+/// A task of a worker thread.
+enum Task {
+    Add(ChainId, Box<Bft<AnySink>>),
+    Remove(ChainId),
+    Input(ChainId, CoreInput),
+}
+
+/// A `FromCore` sink that tags the outputs of an instance with its chain ID, so
+/// that the instances in a group can share one `FromGroup` sink.
+#[derive(Clone, Debug)]
+pub struct ChainSink<S> {
+    chain_id: ChainId,
+    sink: S,
+}
+
+impl<S> ChainSink<S> {
+    /// A function to create a new sink of the chain.
+    pub fn new(chain_id: ChainId, sink: S) -> Self {
+        ChainSink { chain_id, sink }
+    }
+}
+
+impl<S: FromGroup> FromCore for ChainSink<S> {
+    type Error = S::Error;
+
+    fn send_msg(&mut self, msg: CoreOutput) -> ::std::result::Result<(), S::Error> {
+        self.sink.send_msg(ChainMsg {
+            chain_id: self.chain_id,
+            msg,
+        })
+    }
+
+    fn allow_empty_block(&mut self, height: u64) -> bool {
+        self.sink.allow_empty_block(self.chain_id, height)
+    }
+}
+
+/// A group of BFT consensus instances in one process, keyed by chain ID. The
+/// instances share a timer and a pool of worker threads, each of them keeps its
+/// own `FromCore` sink. The instances of a group are in stop step by default as
+/// the ones of `Core`.
+#[derive(Debug)]
+pub struct CoreGroup {
+    workers: Vec<Sender<Task>>,
+    timer_seter: Sender<TimerCmd>,
+    clock: Arc<dyn Clock>,
+    heights: HashMap<ChainId, u64>,
+}
+
+impl CoreGroup {
+    /// A function to create a new core group with the number of worker threads,
+    /// at least one worker is started.
+    pub fn new(workers: usize) -> Self {
+        CoreGroup::with_clock(workers, Arc::new(SystemClock))
+    }
+
+    /// A function to create a new core group whose instances run on the clock.
+    pub fn with_clock(workers: usize, clock: Arc<dyn Clock>) -> Self {
+        let (group2timer, timer4group) = unbounded();
+        let (timer2group, group4timer) = unbounded();
+
+        // start the shared timer.
+        let timer = WaitTimer::new(timer2group, timer4group, Arc::clone(&clock));
+        thread::spawn(move || timer.start());
+
+        let mut senders = Vec::new();
+        let mut timeouts = Vec::new();
+        for _ in 0..workers.max(1) {
+            let (s, r) = unbounded();
+            let (ts, tr) = unbounded();
+            thread::spawn(move || work(r, tr));
+            senders.push(s);
+            timeouts.push(ts);
+        }
+
+        // dispatch the timeouts to the worker of the chain.
+        thread::spawn(move || {
+            for info in group4timer.iter() {
+                let index = worker_index(info.chain_id, timeouts.len());
+                if timeouts[index].send(info).is_err() {
+                    return;
+                }
+            }
+        });
+
+        CoreGroup {
+            workers: senders,
+            timer_seter: group2timer,
+            clock,
+            heights: HashMap::new(),
+        }
+    }
+
+    /// A function to add a consensus instance of the chain to the group.
+    pub fn add_chain<T: FromCore + Send + 'static>(
+        &mut self,
+        chain_id: ChainId,
+        s: T,
+        address: Address,
+    ) -> Result<()> {
+        self.add_chain_with_config(chain_id, s, address, BftConfig::new())
+    }
+
+    /// A function to add a consensus instance of the chain to the group with the
+    /// config. The instance runs on the clock of the group whatever the clock of
+    /// the config is, and the instances of a group run the classic engine only.
+    pub fn add_chain_with_config<T: FromCore + Send + 'static>(
+        &mut self,
+        chain_id: ChainId,
+        s: T,
+        address: Address,
+        mut config: BftConfig,
+    ) -> Result<()> {
+        if self.heights.contains_key(&chain_id) {
+            return Err(BftError::ChainExists);
+        }
+
+        let mut engine = Bft::initialize(AnySink::new(s), self.timer_seter.clone(), address);
+        engine.set_chain_id(chain_id);
+        config.set_clock(Arc::clone(&self.clock));
+        engine.apply_config(config);
+        self.send_task(chain_id, Task::Add(chain_id, Box::new(engine)))?;
+        self.heights.insert(chain_id, INIT_HEIGHT);
+        Ok(())
+    }
+
+    /// A function to remove the consensus instance of the chain from the group.
+    pub fn remove_chain(&mut self, chain_id: ChainId) -> Result<()> {
+        if self.heights.remove(&chain_id).is_none() {
+            return Err(BftError::UnknownChain);
+        }
+        self.send_task(chain_id, Task::Remove(chain_id))
+    }
+
+    /// A function to send BFT message to the consensus instance of the chain.
+    pub fn send_bft_msg(&mut self, msg: ChainMsg<CoreInput>) -> Result<()> {
+        let ChainMsg { chain_id, msg } = msg;
+        let height = *self.heights.get(&chain_id).ok_or(BftError::UnknownChain)?;
+        let status_height = match &msg {
+            CoreInput::Status(s) => Some(s.height),
+            _ => None,
+        };
+
+        self.send_task(chain_id, Task::Input(chain_id, msg))?;
+        if let Some(status_height) = status_height {
+            if height <= status_height {
+                self.heights
+                    .insert(chain_id, status_height.saturating_add(1));
+            }
+        }
+        Ok(())
+    }
+
+    /// A function to get Bft machine height of the chain.
+    pub fn get_height(&self, chain_id: ChainId) -> Option<u64> {
+        self.heights.get(&chain_id).cloned()
+    }
+
+    /// A function to get the chain IDs in the group.
+    pub fn chains(&self) -> Vec<ChainId> {
+        self.heights.keys().cloned().collect()
+    }
+
+    fn send_task(&self, chain_id: ChainId, task: Task) -> Result<()> {
+        self.workers[worker_index(chain_id, self.workers.len())]
+            .send(task)
+            .map_err(|_| BftError::SendMsgErr)
+    }
+}
+
+#[inline]
+fn worker_index(chain_id: ChainId, workers: usize) -> usize {
+    (chain_id % workers as u64) as usize
+}
+
+/// The main loop of a worker thread, it exits once the group is dropped.
+fn work(tasks: Receiver<Task>, timeouts: Receiver<TimeoutInfo>) {
+    let mut engines: HashMap<ChainId, Box<Bft<AnySink>>> = HashMap::new();
+    loop {
+        select! {
+            recv(tasks) -> task => match task {
+                Ok(Task::Add(chain_id, engine)) => {
+                    engines.insert(chain_id, engine);
+                }
+                Ok(Task::Remove(chain_id)) => {
+                    engines.remove(&chain_id);
+                }
+                Ok(Task::Input(chain_id, msg)) => {
+                    if let Some(engine) = engines.get_mut(&chain_id) {
+                        engine.handle_input(msg);
+                    }
+                }
+                Err(_) => return,
+            },
+            recv(timeouts) -> info => {
+                if let Ok(info) = info {
+                    if let Some(engine) = engines.get_mut(&info.chain_id) {
+                        engine.handle_timeout(&info);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::EmptyBlockPolicy,
+        safety::{FileSafetyStore, SafetyStore},
+    };
+    use std::time::{Duration, Instant};
+
+    #[derive(Clone)]
+    struct SendMsg(Sender<ChainMsg<CoreOutput>>);
+
+    impl FromGroup for SendMsg {
+        type Error = ();
+
+        fn send_msg(&mut self, msg: ChainMsg<CoreOutput>) -> ::std::result::Result<(), ()> {
+            self.0.send(msg).map_err(|_| ())
+        }
+    }
+
+    fn input(chain_id: ChainId, msg: CoreInput) -> ChainMsg<CoreInput> {
+        ChainMsg { chain_id, msg }
+    }
+
+    #[test]
+    fn test_group_commits() {
+        let (s, r) = unbounded();
+        let mut group = CoreGroup::new(2);
+        let address = Address::new(vec![0]);
+        for chain_id in 0..3 {
+            let sink = ChainSink::new(chain_id, SendMsg(s.clone()));
+            assert_eq!(group.add_chain(chain_id, sink, address.clone()), Ok(()));
+        }
+
+        for chain_id in 0..3 {
+            let status = CoreInput::Status(Status {
+                height: 0,
+                interval: None,
+                authority_list: vec![Node::new(address.clone())],
+            });
+            let feed = CoreInput::Feed(Feed {
+                height: 1,
+                proposal: Target::new(vec![chain_id as u8]),
+            });
+            group
+                .send_bft_msg(input(chain_id, CoreInput::Start))
+                .unwrap();
+            group.send_bft_msg(input(chain_id, status)).unwrap();
+            group.send_bft_msg(input(chain_id, feed)).unwrap();
+            assert_eq!(group.get_height(chain_id), Some(1));
+        }
+
+        // each chain commits its own proposal before the deadline
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut committed = Vec::new();
+        while committed.len() < 3 {
+            let timeout = deadline
+                .checked_duration_since(Instant::now())
+                .expect("the chains do not commit in time");
+            let msg = r.recv_timeout(timeout).unwrap();
+            match msg.msg {
+                CoreOutput::Commit(commit) => {
+                    assert_eq!(commit.proposal, Target::new(vec![msg.chain_id as u8]));
+                    committed.push(msg.chain_id);
+                }
+                #[cfg(feature = "async_verify")]
+                CoreOutput::Proposal(proposal) => {
+                    let resp = CoreInput::VerifyResp(VerifyResp {
+                        is_pass: true,
+                        proposal: proposal.content,
+                    });
+                    group.send_bft_msg(input(msg.chain_id, resp)).unwrap();
+                }
+                _ => (),
+            }
+        }
+        committed.sort();
+        assert_eq!(committed, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_group_config() {
+        let path = ::std::env::temp_dir().join(format!("bft-group-{}", ::std::process::id()));
+        let _ = ::std::fs::remove_file(&path);
+        let (s, r) = unbounded();
+        let mut group = CoreGroup::new(1);
+        let address = Address::new(vec![0]);
+        let empty = Target::new(vec![0xee]);
+        let mut config = BftConfig::new();
+        config.set_empty_block_policy(EmptyBlockPolicy::Propose(empty.clone()));
+        config
+            .set_safety_store(FileSafetyStore::new(&path))
+            .unwrap();
+        let sink = ChainSink::new(1, SendMsg(s));
+        assert_eq!(
+            group.add_chain_with_config(1, sink, address.clone(), config),
+            Ok(())
+        );
+
+        let status = CoreInput::Status(Status {
+            height: 0,
+            interval: None,
+            authority_list: vec![Node::new(address)],
+        });
+        group.send_bft_msg(input(1, CoreInput::Start)).unwrap();
+        group.send_bft_msg(input(1, status)).unwrap();
+
+        // the chain commits the empty block without a feed, and saves its votes
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let timeout = deadline
+                .checked_duration_since(Instant::now())
+                .expect("the chain does not commit in time");
+            match r.recv_timeout(timeout).unwrap().msg {
+                CoreOutput::Commit(commit) => {
+                    assert_eq!(commit.proposal, empty);
+                    break;
+                }
+                #[cfg(feature = "async_verify")]
+                CoreOutput::Proposal(proposal) => {
+                    let resp = CoreInput::VerifyResp(VerifyResp {
+                        is_pass: true,
+                        proposal: proposal.content,
+                    });
+                    group.send_bft_msg(input(1, resp)).unwrap();
+                }
+                _ => (),
+            }
+        }
+        let state = FileSafetyStore::new(&path).load().unwrap().unwrap();
+        assert_eq!(state.height, 1);
+        assert_eq!(
+            state
+                .get_vote(1, 0, &VoteType::Precommit)
+                .map(|vote| &vote.proposal),
+            Some(&empty)
+        );
+        let _ = ::std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_group_chains() {
+        let (s, _r) = unbounded();
+        let mut group = CoreGroup::new(0);
+        let sink = ChainSink::new(1, SendMsg(s.clone()));
+        assert_eq!(group.add_chain(1, sink, Address::new(vec![0])), Ok(()));
+
+        let sink = ChainSink::new(1, SendMsg(s));
+        assert_eq!(
+            group.add_chain(1, sink, Address::new(vec![0])),
+            Err(BftError::ChainExists)
+        );
+        assert_eq!(
+            group.send_bft_msg(input(2, CoreInput::Start)),
+            Err(BftError::UnknownChain)
+        );
+        assert_eq!(group.get_height(1), Some(INIT_HEIGHT));
+        assert_eq!(group.chains(), vec![1]);
+
+        assert_eq!(group.remove_chain(1), Ok(()));
+        assert_eq!(group.remove_chain(1), Err(BftError::UnknownChain));
+        assert_eq!(group.get_height(1), None);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::types::{Target, Vote, VoteType};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// How many heights of safety records are kept, the last height is needed to
+/// check the retransmitted votes.
+const RECORD_HEIGHTS: u64 = 2;
+
+/// A safety related event of the local node.
+#[derive(Clone, Debug)]
+pub(crate) enum SafetyEvent {
+    /// The node sent a vote.
+    Vote(Vote),
+    /// The node locked on a proposal with a PoLC of the round.
+    Lock { round: u64, proposal: Target },
+    /// The node cleaned its lock in the round.
+    Unlock { round: u64 },
+    /// The node committed the proposal with the precommits of the round.
+    Commit { round: u64, proposal: Target },
+}
+
+impl fmt::Display for SafetyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SafetyEvent::Vote(vote) => write!(
+                f,
+                "send {:?} at round {:?} to {:?}",
+                vote.vote_type, vote.round, vote.proposal
+            ),
+            SafetyEvent::Lock { round, proposal } => {
+                write!(f, "lock on {:?} with PoLC of round {:?}", proposal, round)
+            }
+            SafetyEvent::Unlock { round } => write!(f, "unlock at round {:?}", round),
+            SafetyEvent::Commit { round, proposal } => {
+                write!(f, "commit {:?} at round {:?}", proposal, round)
+            }
+        }
+    }
+}
+
+/// Safety records of a height.
+#[derive(Debug, Default)]
+struct HeightRecord {
+    /// All events of the height in order.
+    trace: Vec<SafetyEvent>,
+    /// The votes sent at the height, K is round and vote type.
+    votes: HashMap<(u64, VoteType), Target>,
+    /// The current lock, round and proposal.
+    lock: Option<(u64, Target)>,
+    /// The latest non-nil precommit, round and proposal.
+    precommit: Option<(u64, Target)>,
+}
+
+/// A checker of the locked-value safety invariants. It records every vote the
+/// local node sends and every change of its lock, and panics with the full trace
+/// of the height once the node is about to send a vote that conflicts with them.
+///
+/// It is enabled in debug builds or with the `invariant_check` feature.
+#[derive(Debug)]
+pub(crate) struct InvariantChecker {
+    enabled: bool,
+    records: BTreeMap<u64, HeightRecord>,
+}
+
+impl InvariantChecker {
+    /// A function to create a new invariant checker.
+    pub(crate) fn new() -> Self {
+        InvariantChecker {
+            enabled: cfg!(any(debug_assertions, feature = "invariant_check")),
+            records: BTreeMap::new(),
+        }
+    }
+
+    /// A function to drop the records that are too old for the new height.
+    pub(crate) fn new_height(&mut self, height: u64) {
+        if !self.enabled {
+            return;
+        }
+        let oldest = height.saturating_sub(RECORD_HEIGHTS - 1);
+        self.records = self.records.split_off(&oldest);
+    }
+
+    /// A function to record a lock on the proposal with a PoLC of the round.
+    pub(crate) fn record_lock(&mut self, height: u64, round: u64, proposal: &Target) {
+        if !self.enabled {
+            return;
+        }
+        let record = self.records.entry(height).or_default();
+        record.lock = Some((round, proposal.clone()));
+        record.trace.push(SafetyEvent::Lock {
+            round,
+            proposal: proposal.clone(),
+        });
+    }
+
+    /// A function to record the commit of the proposal with the precommits of
+    /// the round.
+    pub(crate) fn record_commit(&mut self, height: u64, round: u64, proposal: &Target) {
+        if !self.enabled {
+            return;
+        }
+        let record = self.records.entry(height).or_default();
+        record.trace.push(SafetyEvent::Commit {
+            round,
+            proposal: proposal.clone(),
+        });
+    }
+
+    /// A function to record that the lock is cleaned in the round.
+    pub(crate) fn record_unlock(&mut self, height: u64, round: u64) {
+        if !self.enabled {
+            return;
+        }
+        let record = self.records.entry(height).or_default();
+        record.lock = None;
+        record.trace.push(SafetyEvent::Unlock { round });
+    }
+
+    /// A function to check a vote that the node is about to send, and record it
+    /// if it is safe. Panic if the vote violates any invariant.
+    pub(crate) fn check_vote(&mut self, vote: &Vote) {
+        if !self.enabled {
+            return;
+        }
+        let record = self.records.entry(vote.height).or_default();
+        if let Err(reason) = record.check(vote) {
+            let trace: Vec<String> = record.trace.iter().map(|e| e.to_string()).collect();
+            panic!(
+                "Safety invariant violated at height {:?}: {}\nvote: {:?}\ntrace:\n  {}",
+                vote.height,
+                reason,
+                vote,
+                trace.join("\n  ")
+            );
+        }
+    }
+}
+
+impl HeightRecord {
+    fn check(&mut self, vote: &Vote) -> Result<(), String> {
+        let key = (vote.round, vote.vote_type.clone());
+        if let Some(sent) = self.votes.get(&key) {
+            if *sent == vote.proposal {
+                // retransmit a sent vote
+                return Ok(());
+            }
+            return Err(format!(
+                "{:?} to {:?} conflicts with the sent one to {:?} at round {:?}",
+                vote.vote_type, vote.proposal, sent, vote.round
+            ));
+        }
+
+        if !vote.proposal.is_nil() {
+            if let Some((lock_round, lock_proposal)) = self.lock.as_ref() {
+                if vote.round >= *lock_round && *lock_proposal != vote.proposal {
+                    return Err(format!(
+                        "{:?} to {:?} conflicts with the lock on {:?} of round {:?}",
+                        vote.vote_type, vote.proposal, lock_proposal, lock_round
+                    ));
+                }
+            }
+
+            if vote.vote_type == VoteType::Precommit {
+                if let Some((pre_round, pre_proposal)) = self.precommit.as_ref() {
+                    let unlocked = self.trace.iter().any(|event| match event {
+                        SafetyEvent::Lock { round, proposal } => {
+                            *proposal == vote.proposal
+                                && *round > *pre_round
+                                && *round <= vote.round
+                        }
+                        _ => false,
+                    });
+                    if *pre_proposal != vote.proposal && !unlocked {
+                        return Err(format!(
+                            "precommit to {:?} without a newer PoLC after the precommit to {:?} \
+                             at round {:?}",
+                            vote.proposal, pre_proposal, pre_round
+                        ));
+                    }
+                }
+                let is_latest = match self.precommit.as_ref() {
+                    Some((round, _)) => *round < vote.round,
+                    None => true,
+                };
+                if is_latest {
+                    self.precommit = Some((vote.round, vote.proposal.clone()));
+                }
+            }
+        }
+
+        self.votes.insert(key, vote.proposal.clone());
+        self.trace.push(SafetyEvent::Vote(vote.clone()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Address;
+
+    fn gen_vote(vote_type: VoteType, round: u64, proposal: Vec<u8>) -> Vote {
+        Vote {
+            vote_type,
+            height: 1,
+            round,
+            proposal: Target::new(proposal),
+            voter: Address::new(vec![0]),
+        }
+    }
+
+    fn gen_checker() -> InvariantChecker {
+        let mut checker = InvariantChecker::new();
+        checker.enabled = true;
+        checker
+    }
+
+    #[test]
+    fn test_retransmit_and_relock() {
+        let mut checker = gen_checker();
+        checker.record_lock(1, 0, &Target::new(vec![1]));
+        checker.check_vote(&gen_vote(VoteType::Prevote, 0, vec![1]));
+        checker.check_vote(&gen_vote(VoteType::Precommit, 0, vec![1]));
+        checker.check_vote(&gen_vote(VoteType::Precommit, 0, vec![1]));
+        checker.check_vote(&gen_vote(VoteType::Prevote, 1, vec![]));
+
+        checker.record_lock(1, 2, &Target::new(vec![2]));
+        checker.check_vote(&gen_vote(VoteType::Prevote, 2, vec![2]));
+        checker.check_vote(&gen_vote(VoteType::Precommit, 2, vec![2]));
+
+        checker.new_height(3);
+        assert!(checker.records.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with the lock")]
+    fn test_vote_to_commit_against_lock() {
+        let mut checker = gen_checker();
+        checker.record_lock(1, 0, &Target::new(vec![1]));
+        checker.check_vote(&gen_vote(VoteType::Precommit, 1, vec![1]));
+
+        // a commit does not allow a vote that the node never cast
+        checker.record_commit(1, 1, &Target::new(vec![2]));
+        checker.check_vote(&gen_vote(VoteType::Prevote, 2, vec![2]));
+    }
+
+    #[test]
+    #[should_panic(expected = "without a newer PoLC")]
+    fn test_precommit_without_unlock() {
+        let mut checker = gen_checker();
+        checker.record_lock(1, 0, &Target::new(vec![1]));
+        checker.check_vote(&gen_vote(VoteType::Precommit, 0, vec![1]));
+        checker.record_unlock(1, 1);
+        checker.check_vote(&gen_vote(VoteType::Precommit, 2, vec![2]));
+    }
+}
@@ -58,29 +58,59 @@
 
 #![deny(missing_docs)]
 
+/// BFT aggregate signature of the votes.
+pub mod aggregate;
 /// BFT state machine.
 pub(crate) mod algorithm;
+/// BFT chained state machine in the pipelined HotStuff style.
+pub(crate) mod chained;
+/// BFT clock of the timers and the filters.
+pub mod clock;
+/// BFT core config.
+pub mod config;
 /// BFT core.
 pub mod core;
 /// BFT error.
 pub mod error;
+/// BFT fuzzing entry points.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzz;
+/// BFT core group of multiple consensus instances.
+pub mod group;
+/// BFT safety invariant checker.
+pub(crate) mod invariant;
 /// BFT params include time interval and local address.
 pub(crate) mod params;
 ///
 pub(crate) mod rand;
+/// BFT safety store to prevent double-signing.
+pub mod safety;
 /// BFT timer.
 pub(crate) mod timer;
+/// BFT transport and a TCP implementation.
+pub mod transport;
 /// BFT types.
 pub mod types;
 /// BFT vote set.
 pub(crate) mod voteset;
 
 /// Re-pub BFT core.
+#[cfg(feature = "bls")]
+pub use crate::aggregate::BlsAggregator;
+pub use crate::aggregate::{AggregateProof, Aggregator};
+pub use crate::clock::{Clock, MockClock, SystemClock};
+pub use crate::config::{BftConfig, EmptyBlockPolicy, EngineMode};
 pub use crate::core::Core;
+pub use crate::group::{ChainSink, CoreGroup};
+pub use crate::safety::{FileSafetyStore, SafetyState, SafetyStore};
+pub use crate::transport::{TcpTransport, Transport};
 #[cfg(feature = "async_verify")]
 pub use crate::types::VerifyResp;
 pub use crate::types::{
-    Address, Commit, CoreInput, CoreOutput, Feed, Node, Proposal, Status, Target, Vote,
+    Address, ChainId, ChainMsg, Commit, CompactVotes, CoreInput, CoreOutput, Evidence, Feed,
+    LockStatus, Node, PeerMsg, Proposal, ProposalContent, QuorumCert, RejectReason, RoundChange,
+    Status, Target, Vote, VoteBitmap,
 };
 
 /// BFT core send message.
@@ -89,4 +119,21 @@ pub trait FromCore {
     type Error: ::std::fmt::Debug;
     /// Send a BFT message to outside.
     fn send_msg(&mut self, msg: CoreOutput) -> Result<(), Self::Error>;
+    /// Whether an empty block is allowed at the height, it is asked by a
+    /// proposer without a feed under `EmptyBlockPolicy::Propose`.
+    fn allow_empty_block(&mut self, _height: u64) -> bool {
+        true
+    }
+}
+
+/// BFT core group send message.
+pub trait FromGroup {
+    /// BFT core group send message error.
+    type Error: ::std::fmt::Debug;
+    /// Send a BFT message of a chain to outside.
+    fn send_msg(&mut self, msg: ChainMsg<CoreOutput>) -> Result<(), Self::Error>;
+    /// Whether an empty block is allowed at the height of the chain.
+    fn allow_empty_block(&mut self, _chain_id: ChainId, _height: u64) -> bool {
+        true
+    }
 }
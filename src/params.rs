@@ -1,5 +1,13 @@
-use crate::types::Address;
-use std::{cell::Cell, time::Duration};
+use crate::{
+    clock::{Clock, SystemClock},
+    config::EmptyBlockPolicy,
+    types::Address,
+};
+use std::{cell::Cell, sync::Arc, time::Duration};
+
+/// The upper bound of the total interval in milliseconds, a longer interval is
+/// clamped to keep the timeouts from overflowing.
+const MAX_TOTAL_DURATION: u64 = 24 * 60 * 60 * 1000;
 
 /// BFT params.
 #[derive(Clone, Debug)]
@@ -8,6 +16,16 @@ pub(crate) struct BftParams {
     pub(crate) address: Address,
     /// A set of BFT timer settings.
     pub(crate) timer: BftTimer,
+    /// The clock of the timers and the filters.
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Whether a rejected proposal is reported as evidence.
+    pub(crate) report_evidence: bool,
+    /// The number of the past heights whose authorities are kept.
+    pub(crate) authority_window: u64,
+    /// The policy of a proposer without a feed.
+    pub(crate) empty_block: EmptyBlockPolicy,
+    /// The threshold of the fast path, fraction: (numerator, denominator).
+    pub(crate) fast_commit: Option<(u64, u64)>,
 }
 
 impl BftParams {
@@ -16,6 +34,11 @@ impl BftParams {
         BftParams {
             address: local_address,
             timer: BftTimer::default(),
+            clock: Arc::new(SystemClock),
+            report_evidence: true,
+            authority_window: 1,
+            empty_block: EmptyBlockPolicy::default(),
+            fast_commit: None,
         }
     }
 }
@@ -45,7 +68,7 @@ impl Default for BftTimer {
 impl BftTimer {
     /// A function to set total interval.
     pub(crate) fn set_total_duration(&self, duration: u64) {
-        self.total_duration.set(duration);
+        self.total_duration.set(duration.min(MAX_TOTAL_DURATION));
     }
 
     /// A function to get propose wait duration.
@@ -4,8 +4,8 @@ use rand_core::{RngCore, SeedableRng};
 use rand_pcg::Pcg64Mcg as Pcg;
 
 #[cfg(not(feature = "no_rand"))]
-pub(crate) fn get_index(seed: u64, weight: &[u64]) -> usize {
-    let sum: u64 = weight.iter().sum();
+pub(crate) fn get_index(seed: u64, weight: &[u64]) -> Option<usize> {
+    let sum = weight_sum(weight)?;
     let x = u64::max_value() / sum;
 
     let mut rng = Pcg::seed_from_u64(seed);
@@ -17,23 +17,31 @@ pub(crate) fn get_index(seed: u64, weight: &[u64]) -> usize {
     for (index, w) in weight.iter().enumerate() {
         acc += *w;
         if res < acc * x {
-            return index;
+            return Some(index);
         }
     }
-    0
+    None
 }
 
 #[cfg(feature = "no_rand")]
-pub(crate) fn get_index(seed: u64, weight: &[u64]) -> usize {
-    let sum: u64 = weight.iter().sum();
+pub(crate) fn get_index(seed: u64, weight: &[u64]) -> Option<usize> {
+    let sum = weight_sum(weight)?;
     let x = seed % sum;
 
     let mut acc = 0u64;
     for (index, w) in weight.iter().enumerate() {
         acc += *w;
         if x < acc {
-            return index;
+            return Some(index);
         }
     }
-    0
+    None
+}
+
+/// The weight sum, none if it is zero or overflows.
+fn weight_sum(weight: &[u64]) -> Option<u64> {
+    weight
+        .iter()
+        .try_fold(0u64, |acc, w| acc.checked_add(*w))
+        .filter(|sum| *sum > 0)
 }
@@ -0,0 +1,160 @@
+use crate::types::{LockStatus, QuorumCert, Vote, VoteType};
+
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// The minimum state to prevent double-signing after a restart. It is the votes
+/// that the node signed at the height and its lock.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SafetyState {
+    /// The height of the state.
+    pub height: u64,
+    /// The votes signed at the height.
+    pub votes: Vec<Vote>,
+    /// The lock at the height.
+    pub lock: Option<LockStatus>,
+    /// The locked certificate of the chained mode.
+    pub locked_qc: Option<QuorumCert>,
+}
+
+impl SafetyState {
+    /// A function to get the vote signed at the height, round and vote type.
+    pub fn get_vote(&self, height: u64, round: u64, vote_type: &VoteType) -> Option<&Vote> {
+        if height != self.height {
+            return None;
+        }
+        self.votes
+            .iter()
+            .find(|vote| vote.round == round && vote.vote_type == *vote_type)
+    }
+
+    /// A function to get the latest round of the signed votes and the lock.
+    pub fn latest_round(&self) -> Option<u64> {
+        self.votes
+            .iter()
+            .map(|vote| vote.round)
+            .chain(self.lock.iter().map(|lock| lock.round))
+            .max()
+    }
+}
+
+/// A store of the safety state. BFT core saves the state before it sends a vote
+/// or after it changes the lock, and refuses to sign a vote conflicting with it.
+pub trait SafetyStore: Send + fmt::Debug {
+    /// Load the saved state, none if nothing is saved.
+    fn load(&mut self) -> io::Result<Option<SafetyState>>;
+    /// Save the state, it must be durable once the function returns.
+    fn save(&mut self, state: &SafetyState) -> io::Result<()>;
+}
+
+/// A safety store of a file. The state is written into a temporary file beside
+/// it, then the temporary file is renamed to the file atomically.
+#[derive(Clone, Debug)]
+pub struct FileSafetyStore {
+    path: PathBuf,
+}
+
+impl FileSafetyStore {
+    /// A function to create a new safety store of the file path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileSafetyStore { path: path.into() }
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".tmp");
+        path.into()
+    }
+}
+
+impl SafetyStore for FileSafetyStore {
+    fn load(&mut self) -> io::Result<Option<SafetyState>> {
+        match fs::read(&self.path) {
+            Ok(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&mut self, state: &SafetyState) -> io::Result<()> {
+        let bytes =
+            bincode::serialize(state).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temp = self.temp_path();
+        let mut file = File::create(&temp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+
+        // sync the directory to make the rename durable
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{Address, Target};
+
+    fn gen_vote(vote_type: VoteType, round: u64, proposal: Vec<u8>) -> Vote {
+        Vote {
+            vote_type,
+            height: 1,
+            round,
+            proposal: Target::new(proposal),
+            voter: Address::new(vec![0]),
+        }
+    }
+
+    #[test]
+    fn test_file_store() {
+        let path = ::std::env::temp_dir().join(format!("bft-safety-{}", ::std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut store = FileSafetyStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+
+        let state = SafetyState {
+            height: 1,
+            votes: vec![
+                gen_vote(VoteType::Prevote, 0, vec![1]),
+                gen_vote(VoteType::Precommit, 2, vec![1]),
+            ],
+            lock: Some(LockStatus {
+                proposal: Target::new(vec![1]),
+                round: 2,
+                votes: Vec::new(),
+            }),
+            locked_qc: None,
+        };
+        store.save(&state).unwrap();
+        assert_eq!(
+            FileSafetyStore::new(&path).load().unwrap(),
+            Some(state.clone())
+        );
+        assert!(!store.temp_path().exists());
+
+        assert_eq!(
+            state.get_vote(1, 2, &VoteType::Precommit),
+            Some(&state.votes[1])
+        );
+        assert_eq!(state.get_vote(1, 2, &VoteType::Prevote), None);
+        assert_eq!(state.get_vote(2, 0, &VoteType::Prevote), None);
+        assert_eq!(state.latest_round(), Some(2));
+
+        // a broken file is an error rather than an empty state
+        fs::write(&path, [0xff]).unwrap();
+        assert!(store.load().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,16 +1,28 @@
-use crate::algorithm::Step;
+use crate::{algorithm::Step, clock::Clock, types::ChainId};
 
-use std::cmp::{Ord, Ordering, PartialOrd};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crossbeam_channel::{Receiver, Sender};
-use min_max_heap::MinMaxHeap;
+use crossbeam_channel::{never, select, Receiver, Sender};
+
+/// The bits of the slot index in a level of the timer wheel.
+const SLOT_BITS: u32 = 6;
+/// The count of slots in a level of the timer wheel.
+const SLOTS: usize = 1 << SLOT_BITS;
+/// The count of levels of the timer wheel, it covers 2^42 milliseconds.
+const LEVELS: usize = 7;
+/// The longest delay of a timer in milliseconds, a longer one is clamped.
+const MAX_DELAY: u64 = 1 << 40;
 
 /// Timer infomation.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(crate) struct TimeoutInfo {
     /// A timeval of a timer.
     pub(crate) timeval: Instant,
+    /// The chain ID of the instance which sets the timer.
+    pub(crate) chain_id: ChainId,
     /// The height of the timer.
     pub(crate) height: u64,
     /// The round of the timer.
@@ -19,70 +31,207 @@ pub(crate) struct TimeoutInfo {
     pub(crate) step: Step,
 }
 
-impl PartialOrd for TimeoutInfo {
-    fn partial_cmp(&self, other: &TimeoutInfo) -> Option<Ordering> {
-        self.timeval.partial_cmp(&other.timeval)
+/// The handle of a timer to cancel it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub(crate) struct TimerId {
+    /// The chain ID of the instance which sets the timer.
+    pub(crate) chain_id: ChainId,
+    /// The sequence number of the timer in the instance.
+    pub(crate) seq: u64,
+}
+
+/// A command to the timer.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum TimerCmd {
+    /// Set a timer of the handle.
+    Set(TimerId, TimeoutInfo),
+    /// Cancel the timer of the handle.
+    Cancel(TimerId),
+    /// Cancel all the timers of the chain before the height and round.
+    CancelBefore {
+        /// The chain ID of the timers.
+        chain_id: ChainId,
+        /// The height of the timers to keep.
+        height: u64,
+        /// The round of the timers to keep.
+        round: u64,
+    },
+}
+
+impl TimerCmd {
+    /// A function to check if the command cancels the timer.
+    pub(crate) fn cancels(&self, id: &TimerId, info: &TimeoutInfo) -> bool {
+        match self {
+            TimerCmd::Set(..) => false,
+            TimerCmd::Cancel(cancel) => cancel == id,
+            TimerCmd::CancelBefore {
+                chain_id,
+                height,
+                round,
+            } => info.chain_id == *chain_id && (info.height, info.round) < (*height, *round),
+        }
     }
 }
 
-impl Ord for TimeoutInfo {
-    fn cmp(&self, other: &TimeoutInfo) -> Ordering {
-        self.timeval.cmp(&other.timeval)
+/// A hashed hierarchical timer wheel in milliseconds. A timer is inserted into
+/// the slot of its expiration in O(1), the timers of a higher level are moved to
+/// the lower levels once the wheel reaches their slot.
+///
+/// A cancelled timer is removed from the timer map at once, but its handle
+/// stays in the slot until the wheel reaches the slot and skips it. `Cancel` is
+/// O(1), while `CancelBefore` retains the timer map in O(n) of the pending
+/// timers.
+pub(crate) struct TimerWheel {
+    start: Instant,
+    // the milliseconds since start that the wheel has processed
+    elapsed: u64,
+    levels: Vec<Vec<Vec<TimerId>>>,
+    timers: HashMap<TimerId, (u64, TimeoutInfo)>,
+}
+
+impl TimerWheel {
+    /// A function to create a new timer wheel starting from the instant.
+    pub(crate) fn new(start: Instant) -> Self {
+        TimerWheel {
+            start,
+            elapsed: 0,
+            levels: vec![vec![Vec::new(); SLOTS]; LEVELS],
+            timers: HashMap::new(),
+        }
+    }
+
+    /// A function to apply a command to the wheel.
+    pub(crate) fn apply(&mut self, cmd: TimerCmd) {
+        match cmd {
+            TimerCmd::Set(id, info) => self.insert(id, info),
+            TimerCmd::Cancel(id) => {
+                self.timers.remove(&id);
+            }
+            TimerCmd::CancelBefore { .. } => {
+                self.timers.retain(|id, (_, info)| !cmd.cancels(id, info))
+            }
+        }
+    }
+
+    /// A function to get the instant that the wheel needs to be polled next, it
+    /// is none if there is no timer.
+    pub(crate) fn next_instant(&self) -> Option<Instant> {
+        if self.timers.is_empty() {
+            return None;
+        }
+        self.next_slot()
+            .map(|(tick, _, _)| self.start + Duration::from_millis(tick))
+    }
+
+    /// A function to take the timers expired at the instant.
+    pub(crate) fn poll(&mut self, now: Instant) -> Vec<TimeoutInfo> {
+        let now = self.tick_of(now);
+        let mut res = Vec::new();
+
+        while let Some((tick, level, slot)) = self.next_slot() {
+            if tick > now {
+                break;
+            }
+            self.elapsed = self.elapsed.max(tick);
+            for id in ::std::mem::take(&mut self.levels[level][slot]).into_iter() {
+                let deadline = match self.timers.get(&id) {
+                    Some((deadline, _)) => *deadline,
+                    // the timer is cancelled
+                    None => continue,
+                };
+                if deadline <= self.elapsed {
+                    res.push(self.timers.remove(&id).unwrap().1);
+                } else {
+                    self.place(id, deadline);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(now);
+        res
+    }
+
+    fn insert(&mut self, id: TimerId, info: TimeoutInfo) {
+        let deadline = self
+            .tick_of(info.timeval)
+            .clamp(self.elapsed, self.elapsed + MAX_DELAY);
+        self.timers.insert(id, (deadline, info));
+        self.place(id, deadline);
+    }
+
+    fn place(&mut self, id: TimerId, deadline: u64) {
+        // the level is decided by the highest bit that differs from now
+        let masked = (self.elapsed ^ deadline) | (SLOTS as u64 - 1);
+        let level = ((63 - masked.leading_zeros()) / SLOT_BITS) as usize;
+        let slot = (deadline >> (level as u32 * SLOT_BITS)) as usize % SLOTS;
+        self.levels[level][slot].push(id);
+    }
+
+    /// The first occupied slot and the tick to process it. The slots of a lower
+    /// level are always earlier than the ones of a higher level.
+    fn next_slot(&self) -> Option<(u64, usize, usize)> {
+        for (level, slots) in self.levels.iter().enumerate() {
+            let shift = level as u32 * SLOT_BITS;
+            let current = (self.elapsed >> shift) as usize % SLOTS;
+            // the current slot of a higher level is never occupied
+            let first = if level == 0 { current } else { current + 1 };
+            if let Some(slot) = (first..SLOTS).find(|slot| !slots[*slot].is_empty()) {
+                let base = self.elapsed >> (shift + SLOT_BITS) << (shift + SLOT_BITS);
+                return Some((base + ((slot as u64) << shift), level, slot));
+            }
+        }
+        None
+    }
+
+    fn tick_of(&self, instant: Instant) -> u64 {
+        let duration = instant.saturating_duration_since(self.start);
+        u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
     }
 }
 
 /// Sender and receiver of a timeout infomation channel.
 pub(crate) struct WaitTimer {
-    timer_seter: Receiver<TimeoutInfo>,
+    timer_seter: Receiver<TimerCmd>,
     timer_notify: Sender<TimeoutInfo>,
+    clock: Arc<dyn Clock>,
 }
 
 impl WaitTimer {
     /// A function to create a new timeout infomation channel.
-    pub(crate) fn new(ts: Sender<TimeoutInfo>, rs: Receiver<TimeoutInfo>) -> WaitTimer {
+    pub(crate) fn new(
+        ts: Sender<TimeoutInfo>,
+        rs: Receiver<TimerCmd>,
+        clock: Arc<dyn Clock>,
+    ) -> WaitTimer {
         WaitTimer {
             timer_notify: ts,
             timer_seter: rs,
+            clock,
         }
     }
 
-    /// A function to start a timer.
+    /// A function to start a timer. It sleeps until the next timer expires, or
+    /// until a command arrives if there is no timer.
     pub(crate) fn start(&self) {
-        let mut timer_heap = MinMaxHeap::<TimeoutInfo>::new();
+        let mut wheel = TimerWheel::new(self.clock.now());
 
         loop {
-            // take the peek of the min-heap-timer sub now as the sleep time otherwise set timeout as 100
-            let timeout = if !timer_heap.is_empty() {
-                let peek_min_interval = timer_heap.peek_min().unwrap().timeval;
-                let now = Instant::now();
-                if peek_min_interval > now {
-                    peek_min_interval - now
-                } else {
-                    Duration::new(0, 0)
-                }
-            } else {
-                Duration::from_secs(100)
+            let wake = match wheel.next_instant() {
+                Some(instant) => self.clock.after(instant),
+                None => never(),
             };
 
-            let set_time = self.timer_seter.recv_timeout(timeout);
-
-            // put the timeval into a timerheap
-            // put the TimeoutInfo into a hashmap, K: timeval  V: TimeoutInfo
-            if set_time.is_ok() {
-                let time_out = set_time.unwrap();
-                timer_heap.push(time_out);
+            select! {
+                recv(self.timer_seter) -> cmd => match cmd {
+                    Ok(cmd) => wheel.apply(cmd),
+                    // all the state machines are dropped
+                    Err(_) => return,
+                },
+                recv(wake) -> _ => (),
             }
 
-            if !timer_heap.is_empty() {
-                let now = Instant::now();
-
-                // if some timers are set as the same time, send timeout messages and pop them
-                while !timer_heap.is_empty()
-                    && now >= timer_heap.peek_min().cloned().unwrap().timeval
-                {
-                    self.timer_notify
-                        .send(timer_heap.pop_min().unwrap())
-                        .unwrap();
+            for info in wheel.poll(self.clock.now()).into_iter() {
+                if self.timer_notify.send(info).is_err() {
+                    return;
                 }
             }
         }
@@ -92,13 +241,15 @@ impl WaitTimer {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::clock::MockClock;
     use crossbeam_channel::unbounded;
 
     impl TimeoutInfo {
-        fn new(t: u64, h: u64, r: u64) -> Self {
-            let timeval: Instant = Instant::now() + Duration::from_millis(t);
+        fn new(start: Instant, t: u64, h: u64, r: u64) -> Self {
+            let timeval: Instant = start + Duration::from_millis(t);
             TimeoutInfo {
                 timeval,
+                chain_id: 0,
                 height: h,
                 round: r,
                 step: Step::default(),
@@ -106,35 +257,118 @@ mod test {
         }
     }
 
-    fn gen_timeoutinfo() -> Vec<TimeoutInfo> {
+    fn id(seq: u64) -> TimerId {
+        TimerId { chain_id: 0, seq }
+    }
+
+    fn gen_timeoutinfo(start: Instant) -> Vec<TimeoutInfo> {
         let mut res = Vec::new();
-        res.push(TimeoutInfo::new(150, 0, 0));
-        res.push(TimeoutInfo::new(180, 2, 1));
-        res.push(TimeoutInfo::new(50, 3, 6));
+        res.push(TimeoutInfo::new(start, 150, 0, 0));
+        res.push(TimeoutInfo::new(start, 180, 2, 1));
+        res.push(TimeoutInfo::new(start, 50, 3, 6));
         res
     }
 
     #[test]
-    fn test_timer_heap() {
+    fn test_wait_timer() {
         let (s_1, r_1) = unbounded();
         let (s_2, r_2) = unbounded();
+        let clock = MockClock::new(Instant::now());
+        let timer = WaitTimer::new(s_2, r_1, Arc::new(clock.clone()));
+        ::std::thread::spawn(move || timer.start());
 
-        ::std::thread::spawn(move || {
-            let timer = WaitTimer::new(s_2, r_1);
-            timer.start();
-        });
+        let infos = gen_timeoutinfo(clock.now());
+        for (seq, ti) in infos.clone().into_iter().enumerate() {
+            s_1.send(TimerCmd::Set(id(seq as u64), ti)).unwrap();
+        }
+        let recv = || r_2.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // the timers expire in the virtual time of the clock
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(infos[2], recv());
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(infos[0], recv());
+        clock.advance(Duration::from_millis(30));
+        assert_eq!(infos[1], recv());
+    }
+
+    #[test]
+    fn test_timer_wheel() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let info = |ms: u64, height: u64, round: u64| TimeoutInfo {
+            timeval: at(ms),
+            chain_id: 0,
+            height,
+            round,
+            step: Step::default(),
+        };
+
+        // timers in each level of the wheel, and one beyond the longest delay
+        let delays = [
+            0,
+            3,
+            63,
+            64,
+            4_095,
+            5_000,
+            300_000,
+            86_400_000,
+            u64::MAX >> 20,
+        ];
+        let mut wheel = TimerWheel::new(start);
+        for (seq, delay) in delays.iter().enumerate() {
+            wheel.apply(TimerCmd::Set(id(seq as u64), info(*delay, 1, 0)));
+        }
 
-        let infos = gen_timeoutinfo();
-        for ti in infos.clone().into_iter() {
-            s_1.send(ti).unwrap();
+        for (seq, delay) in delays.iter().enumerate().take(delays.len() - 1) {
+            // the wheel wakes up no later than the next timer
+            assert!(wheel.next_instant().unwrap() <= at(*delay));
+            if *delay > 0 {
+                assert!(wheel.poll(at(delay - 1)).is_empty());
+            }
+            assert_eq!(wheel.poll(at(*delay)), vec![info(*delay, 1, 0)]);
+            assert_eq!(wheel.timers.len(), delays.len() - seq - 1);
         }
-        let now = Instant::now();
-
-        assert_eq!(infos[2], r_2.recv().unwrap());
-        println!("{:?}", Instant::now() - now);
-        assert_eq!(infos[0], r_2.recv().unwrap());
-        println!("{:?}", Instant::now() - now);
-        assert_eq!(infos[1], r_2.recv().unwrap());
-        println!("{:?}", Instant::now() - now);
+        assert_eq!(wheel.poll(at(MAX_DELAY)).len(), 1);
+        assert_eq!(wheel.next_instant(), None);
+    }
+
+    #[test]
+    fn test_timer_cancel() {
+        let start = Instant::now();
+        let info = |ms: u64, chain_id: ChainId, height: u64, round: u64| TimeoutInfo {
+            timeval: start + Duration::from_millis(ms),
+            chain_id,
+            height,
+            round,
+            step: Step::default(),
+        };
+
+        let mut wheel = TimerWheel::new(start);
+        wheel.apply(TimerCmd::Set(id(0), info(10, 0, 1, 0)));
+        wheel.apply(TimerCmd::Set(id(1), info(20, 0, 1, 1)));
+        wheel.apply(TimerCmd::Set(id(2), info(30, 0, 2, 0)));
+        wheel.apply(TimerCmd::Set(id(3), info(40, 0, 2, 1)));
+        let other = TimerId {
+            chain_id: 1,
+            seq: 0,
+        };
+        wheel.apply(TimerCmd::Set(other, info(50, 1, 1, 0)));
+        assert_eq!(wheel.timers.len(), 5);
+
+        // cancel the timers of the chain before height 2, round 0
+        wheel.apply(TimerCmd::CancelBefore {
+            chain_id: 0,
+            height: 2,
+            round: 0,
+        });
+        assert_eq!(wheel.timers.len(), 3);
+        wheel.apply(TimerCmd::Cancel(id(3)));
+        assert_eq!(wheel.timers.len(), 2);
+
+        let expired = wheel.poll(start + Duration::from_millis(100));
+        assert_eq!(expired, vec![info(30, 0, 2, 0), info(50, 1, 1, 0)]);
+        assert_eq!(wheel.timers.len(), 0);
     }
 }
@@ -0,0 +1,576 @@
+use crate::{
+    aggregate::{vote_message, Aggregator},
+    error::BftError,
+    types::*,
+    FromCore,
+};
+
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use log::{debug, error, info, warn};
+use lru_cache::LruCache;
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// The number of frames queued for a peer, the later ones are dropped.
+const PEER_QUEUE_LEN: usize = 1024;
+/// The number of received messages queued for the core, the later ones are
+/// dropped.
+pub(crate) const INBOUND_QUEUE_LEN: usize = 4096;
+/// The number of vote signatures kept to relay the votes in a proposal.
+const VOTE_SIGNATURE_CACHE_LEN: usize = 8192;
+const MIN_RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(10);
+
+/// A transport delivers consensus messages between nodes.
+pub trait Transport {
+    /// Transport error.
+    type Error: ::std::fmt::Debug;
+    /// Broadcast a message to all peers.
+    fn broadcast(&mut self, msg: PeerMsg) -> Result<(), Self::Error>;
+    /// Send a message to the peer of the address.
+    fn send_to(&mut self, to: &Address, msg: PeerMsg) -> Result<(), Self::Error>;
+    /// Get the stream of messages received from peers. The core trusts the
+    /// sender of a message and the votes a proposal carries, so a transport
+    /// must only yield the messages whose signatures are checked.
+    fn incoming(&self) -> Receiver<PeerMsg>;
+}
+
+/// A message sink that sends consensus messages through the transport and the
+/// others to the application.
+pub(crate) struct Router<T, N> {
+    app: T,
+    transport: N,
+}
+
+impl<T, N> Router<T, N> {
+    /// A function to create a new router.
+    pub(crate) fn new(app: T, transport: N) -> Self {
+        Router { app, transport }
+    }
+}
+
+impl<T, N> FromCore for Router<T, N>
+where
+    T: FromCore,
+    N: Transport,
+{
+    type Error = BftError;
+
+    fn send_msg(&mut self, msg: CoreOutput) -> Result<(), BftError> {
+        let res = match msg {
+            CoreOutput::Proposal(p) => self
+                .transport
+                .broadcast(PeerMsg::Proposal(p))
+                .map_err(|e| error!("Transport broadcast error {:?}", e)),
+            CoreOutput::Vote(v) => self
+                .transport
+                .broadcast(PeerMsg::Vote(v))
+                .map_err(|e| error!("Transport broadcast error {:?}", e)),
+            CoreOutput::RoundChange(rc) => self
+                .transport
+                .broadcast(PeerMsg::RoundChange(rc))
+                .map_err(|e| error!("Transport broadcast error {:?}", e)),
+            CoreOutput::SendTo { to, msg } => self
+                .transport
+                .send_to(&to, msg)
+                .map_err(|e| error!("Transport send to {:?} error {:?}", to, e)),
+            _ => self
+                .app
+                .send_msg(msg)
+                .map_err(|e| error!("Send message to application error {:?}", e)),
+        };
+        res.map_err(|_| BftError::SendMsgErr)
+    }
+
+    fn allow_empty_block(&mut self, height: u64) -> bool {
+        self.app.allow_empty_block(height)
+    }
+}
+
+/// A frame on the wire, the message with the signature of its sender and the
+/// signatures of the votes it carries.
+#[derive(Serialize, Deserialize)]
+struct SignedMsg {
+    msg: PeerMsg,
+    signature: Vec<u8>,
+    vote_signatures: Vec<Vec<u8>>,
+}
+
+/// The signatures of the votes sent or received, keyed by the vote.
+type SignatureCache = Arc<Mutex<LruCache<Vote, Vec<u8>>>>;
+
+/// A thread based TCP transport with a static peer list.
+///
+/// Every peer has a writer thread with a bounded queue, which connects lazily
+/// and reconnects after a failure. The reconnects back off exponentially, the
+/// messages to an unreachable peer or beyond the queue are dropped, the BFT
+/// state machine retransmits what it needs.
+///
+/// Every message is signed by the aggregator. A received message is dropped
+/// and its connection closed, unless it is signed by its sender and every vote
+/// a proposal carries is signed by its voter. A vote signs the message of
+/// `vote_message`, so the signatures of the votes it keeps can be aggregated.
+pub struct TcpTransport {
+    local_addr: SocketAddr,
+    peers: HashMap<Address, Sender<Arc<Vec<u8>>>>,
+    receiver: Receiver<PeerMsg>,
+    aggregator: Arc<dyn Aggregator>,
+    signatures: SignatureCache,
+}
+
+impl TcpTransport {
+    /// A function to create a new TCP transport. It listens on `listen` and
+    /// sends messages to `peers`, the entry of the local address is skipped.
+    /// The aggregator signs with the key of the local address.
+    pub fn new(
+        address: &Address,
+        listen: SocketAddr,
+        peers: Vec<(Address, SocketAddr)>,
+        aggregator: Arc<dyn Aggregator>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(listen)?;
+        let local_addr = listener.local_addr()?;
+        let (sender, receiver) = bounded(INBOUND_QUEUE_LEN);
+        let signatures = Arc::new(Mutex::new(LruCache::new(VOTE_SIGNATURE_CACHE_LEN)));
+        let verifier = Verifier {
+            aggregator: Arc::clone(&aggregator),
+            signatures: Arc::clone(&signatures),
+        };
+        thread::spawn(move || accept_loop(listener, sender, verifier));
+        info!("TCP transport listen on {:?}", local_addr);
+
+        let mut peer_map = HashMap::new();
+        for (addr, socket) in peers.into_iter().filter(|(addr, _)| addr != address) {
+            let (s, r) = bounded(PEER_QUEUE_LEN);
+            thread::spawn(move || write_loop(socket, r));
+            peer_map.insert(addr, s);
+        }
+
+        Ok(TcpTransport {
+            local_addr,
+            peers: peer_map,
+            receiver,
+            aggregator,
+            signatures,
+        })
+    }
+
+    /// A function to get the listening socket address.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// A function to sign the message and encode it into a frame. The votes
+    /// a proposal carries must have been sent or received before.
+    fn encode(&self, msg: PeerMsg) -> Result<Arc<Vec<u8>>, BftError> {
+        let signature = self.aggregator.sign(&signed_message(&msg)?);
+        let mut signatures = self.signatures.lock().unwrap();
+        if let PeerMsg::Vote(vote) = &msg {
+            signatures.insert(vote.clone(), signature.clone());
+        }
+        let vote_signatures = relayed_votes(&msg)
+            .into_iter()
+            .map(|vote| signatures.get_mut(vote).cloned())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                warn!("No signature of a vote in the proposal");
+                BftError::SendMsgErr
+            })?;
+        drop(signatures);
+
+        let frame = SignedMsg {
+            msg,
+            signature,
+            vote_signatures,
+        };
+        bincode::serialize(&frame)
+            .map(Arc::new)
+            .map_err(|_| BftError::MsgTypeErr)
+    }
+
+    /// A function to queue the frame for the peer, it is dropped if the queue
+    /// is full.
+    fn enqueue(sender: &Sender<Arc<Vec<u8>>>, frame: Arc<Vec<u8>>) -> Result<(), BftError> {
+        match sender.try_send(frame) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                debug!("The queue of a peer is full, drop the message");
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(BftError::SendMsgErr),
+        }
+    }
+}
+
+impl Transport for TcpTransport {
+    type Error = BftError;
+
+    fn broadcast(&mut self, msg: PeerMsg) -> Result<(), BftError> {
+        let frame = self.encode(msg)?;
+        for sender in self.peers.values() {
+            TcpTransport::enqueue(sender, Arc::clone(&frame))?;
+        }
+        Ok(())
+    }
+
+    fn send_to(&mut self, to: &Address, msg: PeerMsg) -> Result<(), BftError> {
+        let sender = self.peers.get(to).ok_or(BftError::SendMsgErr)?;
+        let frame = self.encode(msg)?;
+        TcpTransport::enqueue(sender, frame)
+    }
+
+    fn incoming(&self) -> Receiver<PeerMsg> {
+        self.receiver.clone()
+    }
+}
+
+/// The message to sign of a peer message. A vote signs the message of
+/// `vote_message`, the first byte of the others tells them apart from it.
+fn signed_message(msg: &PeerMsg) -> Result<Vec<u8>, BftError> {
+    let (tag, body) = match msg {
+        PeerMsg::Vote(vote) => return Ok(vote_message(vote)),
+        PeerMsg::Proposal(p) => (3u8, bincode::serialize(p)),
+        PeerMsg::RoundChange(rc) => (4u8, bincode::serialize(rc)),
+    };
+    let mut res = vec![tag];
+    res.extend(body.map_err(|_| BftError::MsgTypeErr)?);
+    Ok(res)
+}
+
+fn sender_of(msg: &PeerMsg) -> &Address {
+    match msg {
+        PeerMsg::Proposal(p) => &p.proposer,
+        PeerMsg::Vote(v) => &v.voter,
+        PeerMsg::RoundChange(rc) => &rc.voter,
+    }
+}
+
+/// The votes a proposal carries, the lock votes and the votes of the parent
+/// certificate.
+fn relayed_votes(msg: &PeerMsg) -> Vec<&Vote> {
+    match msg {
+        PeerMsg::Proposal(p) => p
+            .lock_votes
+            .iter()
+            .chain(p.parent_qc.iter().flat_map(|qc| qc.votes.iter()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The checker of the signatures of the received messages.
+#[derive(Clone)]
+struct Verifier {
+    aggregator: Arc<dyn Aggregator>,
+    signatures: SignatureCache,
+}
+
+impl Verifier {
+    /// A function to check the signatures of the frame, the signatures of the
+    /// votes are kept once the frame is valid.
+    fn verify(&self, frame: SignedMsg) -> Option<PeerMsg> {
+        let msg = signed_message(&frame.msg).ok()?;
+        if !self
+            .aggregator
+            .verify(&msg, sender_of(&frame.msg), &frame.signature)
+        {
+            return None;
+        }
+        let votes = relayed_votes(&frame.msg);
+        if votes.len() != frame.vote_signatures.len()
+            || votes
+                .iter()
+                .zip(frame.vote_signatures.iter())
+                .any(|(vote, signature)| {
+                    !self
+                        .aggregator
+                        .verify(&vote_message(vote), &vote.voter, signature)
+                })
+        {
+            return None;
+        }
+
+        let mut signatures = self.signatures.lock().unwrap();
+        for (vote, signature) in votes.into_iter().zip(frame.vote_signatures) {
+            signatures.insert(vote.clone(), signature);
+        }
+        if let PeerMsg::Vote(vote) = &frame.msg {
+            signatures.insert(vote.clone(), frame.signature);
+        }
+        Some(frame.msg)
+    }
+}
+
+fn accept_loop(listener: TcpListener, sender: Sender<PeerMsg>, verifier: Verifier) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let sender = sender.clone();
+                let verifier = verifier.clone();
+                thread::spawn(move || read_loop(stream, sender, verifier));
+            }
+            Err(e) => warn!("Accept TCP connection error {:?}", e),
+        }
+    }
+}
+
+fn read_loop(mut stream: TcpStream, sender: Sender<PeerMsg>, verifier: Verifier) {
+    loop {
+        let frame = match read_frame(&mut stream) {
+            Ok(frame) => frame,
+            Err(e) => {
+                debug!("TCP connection {:?} closed, {:?}", stream.peer_addr(), e);
+                return;
+            }
+        };
+
+        let msg = match bincode::deserialize::<SignedMsg>(&frame) {
+            Ok(frame) => match verifier.verify(frame) {
+                Some(msg) => msg,
+                None => {
+                    warn!("Invalid signature from {:?}", stream.peer_addr());
+                    return;
+                }
+            },
+            Err(e) => {
+                warn!("Decode message from {:?} error {:?}", stream.peer_addr(), e);
+                return;
+            }
+        };
+        match sender.try_send(msg) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => debug!("The inbound queue is full, drop the message"),
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+    }
+}
+
+fn write_loop(socket: SocketAddr, receiver: Receiver<Arc<Vec<u8>>>) {
+    let mut stream: Option<TcpStream> = None;
+    // no connect is tried before the instant, the backoff doubles every failure
+    let mut retry_at = Instant::now();
+    let mut backoff = MIN_RECONNECT_BACKOFF;
+    for frame in receiver.iter() {
+        if stream.is_none() && Instant::now() >= retry_at {
+            match TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT) {
+                Ok(s) => {
+                    let _ = s.set_nodelay(true);
+                    stream = Some(s);
+                    backoff = MIN_RECONNECT_BACKOFF;
+                }
+                Err(e) => {
+                    debug!(
+                        "Connect to {:?} error {:?}, retry after {:?}",
+                        socket, e, backoff
+                    );
+                    retry_at = Instant::now() + backoff;
+                    backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+                }
+            }
+        }
+
+        if let Some(s) = stream.as_mut() {
+            if let Err(e) = write_frame(s, &frame) {
+                warn!("Send message to {:?} error {:?}", socket, e);
+                stream = None;
+            }
+        }
+    }
+}
+
+fn read_frame(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+    }
+    // the frame grows as the bytes arrive rather than by the claimed length
+    let mut frame = Vec::new();
+    stream.take(len as u64).read_to_end(&mut frame)?;
+    if frame.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "frame too short",
+        ));
+    }
+    Ok(frame)
+}
+
+fn write_frame(stream: &mut TcpStream, frame: &[u8]) -> io::Result<()> {
+    stream.write_all(&(frame.len() as u32).to_be_bytes())?;
+    stream.write_all(frame)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A toy scheme whose signature is the signer and the message.
+    #[derive(Debug)]
+    struct Toy(Address);
+
+    fn sign_by(signer: &Address, msg: &[u8]) -> Vec<u8> {
+        let mut signature = signer.clone().into_vec();
+        signature.extend_from_slice(msg);
+        signature
+    }
+
+    impl Aggregator for Toy {
+        fn sign(&self, msg: &[u8]) -> Vec<u8> {
+            sign_by(&self.0, msg)
+        }
+
+        fn verify(&self, msg: &[u8], signer: &Address, signature: &[u8]) -> bool {
+            sign_by(signer, msg) == signature
+        }
+
+        fn aggregate(&self, _signatures: &[&[u8]]) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn verify_aggregate(&self, _msg: &[u8], _signers: &[&Address], _sig: &[u8]) -> bool {
+            false
+        }
+    }
+
+    fn gen_vote(voter: u8) -> Vote {
+        Vote {
+            vote_type: VoteType::Prevote,
+            height: 1,
+            round: 0,
+            proposal: Target::new(vec![1, 2, 3]),
+            voter: Address::new(vec![voter]),
+        }
+    }
+
+    fn gen_proposal(proposer: u8, lock_votes: Vec<Vote>) -> PeerMsg {
+        PeerMsg::Proposal(Proposal {
+            height: 1,
+            round: 1,
+            content: Target::new(vec![1, 2, 3]),
+            lock_round: Some(0),
+            lock_votes,
+            proposer: Address::new(vec![proposer]),
+            parent_qc: None,
+        })
+    }
+
+    fn new_transport(address: &Address, peers: Vec<(Address, SocketAddr)>) -> TcpTransport {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        TcpTransport::new(address, any, peers, Arc::new(Toy(address.clone()))).unwrap()
+    }
+
+    #[test]
+    fn test_tcp_transport() {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let addr_0 = Address::new(vec![0]);
+        let addr_1 = Address::new(vec![1]);
+
+        let transport_0 = new_transport(&addr_0, vec![]);
+        let mut transport_1 = new_transport(
+            &addr_1,
+            vec![
+                (addr_0.clone(), transport_0.local_addr()),
+                (addr_1.clone(), any),
+            ],
+        );
+        assert_eq!(transport_1.peers.len(), 1);
+
+        let incoming = transport_0.incoming();
+        let vote = PeerMsg::Vote(gen_vote(1));
+        transport_1.send_to(&addr_0, vote.clone()).unwrap();
+        transport_1.broadcast(vote.clone()).unwrap();
+        assert_eq!(incoming.recv().unwrap(), vote);
+        assert_eq!(incoming.recv().unwrap(), vote);
+        assert!(transport_1.send_to(&addr_1, vote).is_err());
+
+        // a proposal can carry the votes whose signatures are known only
+        let proposal = gen_proposal(1, vec![gen_vote(2)]);
+        assert_eq!(transport_1.broadcast(proposal), Err(BftError::SendMsgErr));
+        let proposal = gen_proposal(1, vec![gen_vote(1)]);
+        transport_1.broadcast(proposal.clone()).unwrap();
+        assert_eq!(incoming.recv().unwrap(), proposal);
+    }
+
+    #[test]
+    fn test_reject_forged_message() {
+        let addr_0 = Address::new(vec![0]);
+        let transport_0 = new_transport(&addr_0, vec![]);
+        let incoming = transport_0.incoming();
+        let send = |frame: SignedMsg| {
+            let mut stream = TcpStream::connect(transport_0.local_addr()).unwrap();
+            write_frame(&mut stream, &bincode::serialize(&frame).unwrap()).unwrap();
+            stream
+        };
+        let is_closed = |mut stream: TcpStream| {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(3)))
+                .unwrap();
+            matches!(stream.read(&mut [0u8; 1]), Ok(0))
+        };
+
+        // a vote signed by another node
+        let vote = gen_vote(1);
+        let stream = send(SignedMsg {
+            msg: PeerMsg::Vote(vote.clone()),
+            signature: sign_by(&Address::new(vec![2]), &vote_message(&vote)),
+            vote_signatures: Vec::new(),
+        });
+        assert!(is_closed(stream));
+
+        // a proposal whose lock vote is not signed by the voter
+        let msg = gen_proposal(1, vec![gen_vote(2)]);
+        let stream = send(SignedMsg {
+            signature: sign_by(&Address::new(vec![1]), &signed_message(&msg).unwrap()),
+            msg,
+            vote_signatures: vec![sign_by(&Address::new(vec![1]), &vote_message(&gen_vote(2)))],
+        });
+        assert!(is_closed(stream));
+
+        // a frame beyond the limit is refused before it is read
+        let mut stream = TcpStream::connect(transport_0.local_addr()).unwrap();
+        stream
+            .write_all(&(MAX_FRAME_LEN as u32 + 1).to_be_bytes())
+            .unwrap();
+        assert!(is_closed(stream));
+        assert!(incoming.try_recv().is_err());
+
+        // a message signed by its sender is received
+        let stream = send(SignedMsg {
+            msg: PeerMsg::Vote(vote.clone()),
+            signature: sign_by(&vote.voter, &vote_message(&vote)),
+            vote_signatures: Vec::new(),
+        });
+        assert_eq!(
+            incoming.recv_timeout(Duration::from_secs(3)),
+            Ok(PeerMsg::Vote(vote))
+        );
+        drop(stream);
+    }
+
+    #[test]
+    fn test_unreachable_peer() {
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        // a port which nobody listens on
+        let closed = TcpListener::bind(any).unwrap().local_addr().unwrap();
+        let addr_0 = Address::new(vec![0]);
+        let mut transport = new_transport(&addr_0, vec![(Address::new(vec![1]), closed)]);
+
+        // the frames beyond the queue are dropped instead of blocking
+        let start = Instant::now();
+        for _ in 0..PEER_QUEUE_LEN * 4 {
+            transport.broadcast(PeerMsg::Vote(gen_vote(0))).unwrap();
+        }
+        assert!(start.elapsed() < CONNECT_TIMEOUT);
+    }
+}
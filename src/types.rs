@@ -1,3 +1,5 @@
+use crate::error::BftError;
+
 use serde_derive::{Deserialize, Serialize};
 use std::{collections::HashMap, fmt};
 
@@ -64,6 +66,19 @@ impl Target {
     }
 }
 
+/// Type for the ID of a chain, it tells apart the consensus instances in a
+/// `CoreGroup`.
+pub type ChainId = u64;
+
+/// A message of the consensus instance of a chain in a `CoreGroup`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChainMsg<M> {
+    /// The chain ID of the instance.
+    pub chain_id: ChainId,
+    /// The message.
+    pub msg: M,
+}
+
 /// BFT input message type.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum CoreInput {
@@ -76,6 +91,10 @@ pub enum CoreInput {
     /// Verify response
     #[cfg(feature = "async_verify")]
     VerifyResp(VerifyResp),
+    /// Round change message.
+    RoundChange(RoundChange),
+    /// The content of a requested proposal is available.
+    ProposalContent(ProposalContent),
     /// Status message, rich status.
     Status(Status),
     /// Commit message.
@@ -95,8 +114,62 @@ pub enum CoreOutput {
     Vote(Vote),
     /// Feed messge, this is the proposal of the height.
     Commit(Commit),
+    /// The last commit extended with a late precommit. It is sent until the
+    /// proposal of the next height, so the proposal can carry all the
+    /// precommits of the last height.
+    CommitUpdate(Commit),
+    /// Round change message.
+    RoundChange(RoundChange),
     /// Request a feed of a height.
     GetProposalRequest(u64),
+    /// Request a feed of the next height early. It is sent on commit if the
+    /// node will propose at round 0 of the next height with the current
+    /// authorities, so the application can build the proposal while it applies
+    /// the commit.
+    PrepareProposal {
+        /// The height of the proposal.
+        height: u64,
+    },
+    /// Request the content of a proposal which reaches a quorum of votes but
+    /// is never received as a proposal.
+    RequestProposal {
+        /// The height of the proposal.
+        height: u64,
+        /// The round of the quorum.
+        round: u64,
+        /// The digest of the proposal.
+        digest: Target,
+    },
+    /// Send a message to the node of the address only.
+    SendTo {
+        /// The address of the receiver.
+        to: Address,
+        /// The message to send.
+        msg: PeerMsg,
+    },
+    /// A proposal is rejected as the proposer misbehaves.
+    Evidence(Evidence),
+}
+
+/// A consensus message transmitted between nodes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum PeerMsg {
+    /// Proposal message.
+    Proposal(Proposal),
+    /// Vote message.
+    Vote(Vote),
+    /// Round change message.
+    RoundChange(RoundChange),
+}
+
+impl From<PeerMsg> for CoreInput {
+    fn from(msg: PeerMsg) -> Self {
+        match msg {
+            PeerMsg::Proposal(p) => CoreInput::Proposal(p),
+            PeerMsg::Vote(v) => CoreInput::Vote(v),
+            PeerMsg::RoundChange(rc) => CoreInput::RoundChange(rc),
+        }
+    }
 }
 
 /// Bft vote types.
@@ -106,6 +179,8 @@ pub enum VoteType {
     Prevote,
     /// Vote type precommit.
     Precommit,
+    /// Vote type of the chained mode, it is the only vote on a proposal.
+    Generic,
 }
 
 /// A proposal
@@ -125,17 +200,35 @@ pub struct Proposal {
     pub lock_votes: Vec<Vote>,
     /// The address of proposer.
     pub proposer: Address,
+    /// The quorum certificate of the parent proposal in the chained mode. It is
+    /// `None` in the default mode, or if the parent is committed.
+    #[serde(default)]
+    pub parent_qc: Option<QuorumCert>,
+}
+
+impl Proposal {
+    /// A function to encode the lock votes into a bitmap of the authority
+    /// list. If the proposal has not been locked, it is `None`.
+    pub fn compact_lock_votes(
+        &self,
+        authority_list: &[Node],
+    ) -> Result<Option<CompactVotes>, BftError> {
+        if self.lock_round.is_none() {
+            return Ok(None);
+        }
+        CompactVotes::from_votes(authority_list, &self.lock_votes).map(Some)
+    }
 }
 
 /// A PoLC.
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub(crate) struct LockStatus {
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LockStatus {
     /// The lock proposal.
-    pub(crate) proposal: Target,
+    pub proposal: Target,
     /// The lock round.
-    pub(crate) round: u64,
+    pub round: u64,
     /// The lock votes.
-    pub(crate) votes: Vec<Vote>,
+    pub votes: Vec<Vote>,
 }
 
 /// A vote to a proposal.
@@ -153,6 +246,207 @@ pub struct Vote {
     pub voter: Address,
 }
 
+/// A quorum certificate of the chained mode. It is the generic votes on a
+/// proposal from the authorities with more than 2/3 of the vote weight.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct QuorumCert {
+    /// The height of the certified proposal.
+    pub height: u64,
+    /// The round of the votes.
+    pub round: u64,
+    /// The certified proposal.
+    pub proposal: Target,
+    /// The generic votes on the proposal.
+    pub votes: Vec<Vote>,
+}
+
+/// A bitmap of the voters, bit `i` is set if the `i`th node of the authority
+/// list votes.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VoteBitmap {
+    len: u64,
+    bits: Vec<u8>,
+}
+
+impl VoteBitmap {
+    /// A function to create an empty bitmap of an authority list of the length.
+    pub fn new(len: usize) -> Self {
+        VoteBitmap {
+            len: len as u64,
+            bits: vec![0; len.div_ceil(8)],
+        }
+    }
+
+    /// A function to get the length of the authority list.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Return true if the authority list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// A function to set the bit of the index, return false if the index is
+    /// out of the list.
+    pub fn set(&mut self, index: usize) -> bool {
+        if index >= self.len() {
+            return false;
+        }
+        self.bits[index / 8] |= 1 << (index % 8);
+        true
+    }
+
+    /// Return true if the bit of the index is set.
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.len() && self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// A function to get the number of the voters.
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// A function to iterate over the indexes of the voters.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len()).filter(move |i| self.contains(*i))
+    }
+
+    /// Return true if the bitmap is of the authority list, a decoded bitmap
+    /// may be malformed.
+    fn is_valid_for(&self, authority_list: &[Node]) -> bool {
+        self.len() == authority_list.len()
+            && self.bits.len() == self.len().div_ceil(8)
+            && self.count() == self.iter().count()
+    }
+}
+
+/// The votes of one type, height, round and proposal in a compact encoding,
+/// the voters are a bitmap of the authority list.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CompactVotes {
+    /// The type of the votes.
+    pub vote_type: VoteType,
+    /// The height of the votes.
+    pub height: u64,
+    /// The round of the votes.
+    pub round: u64,
+    /// The proposal of the votes.
+    pub proposal: Target,
+    /// The voters in the authority list.
+    pub voters: VoteBitmap,
+}
+
+impl CompactVotes {
+    /// A function to encode the votes, such as a PoLC. The votes must be of
+    /// one type, height, round and proposal, and from distinct nodes of the
+    /// authority list.
+    pub fn from_votes(authority_list: &[Node], votes: &[Vote]) -> Result<Self, BftError> {
+        let first = votes.first().ok_or(BftError::InvalidProof)?;
+        let mut voters = VoteBitmap::new(authority_list.len());
+        for vote in votes.iter() {
+            if vote.vote_type != first.vote_type
+                || vote.height != first.height
+                || vote.round != first.round
+                || vote.proposal != first.proposal
+            {
+                return Err(BftError::InvalidProof);
+            }
+            let index = authority_list
+                .iter()
+                .position(|node| node.address == vote.voter)
+                .ok_or(BftError::InvalidProof)?;
+            if voters.contains(index) {
+                return Err(BftError::InvalidProof);
+            }
+            voters.set(index);
+        }
+
+        Ok(CompactVotes {
+            vote_type: first.vote_type.clone(),
+            height: first.height,
+            round: first.round,
+            proposal: first.proposal.clone(),
+            voters,
+        })
+    }
+
+    /// A function to get the voters in the authority list.
+    pub fn voters<'a>(&self, authority_list: &'a [Node]) -> Result<Vec<&'a Node>, BftError> {
+        if !self.voters.is_valid_for(authority_list) {
+            return Err(BftError::InvalidProof);
+        }
+        Ok(self.voters.iter().map(|i| &authority_list[i]).collect())
+    }
+
+    /// A function to decode the full votes with the authority list.
+    pub fn to_votes(&self, authority_list: &[Node]) -> Result<Vec<Vote>, BftError> {
+        Ok(self
+            .voters(authority_list)?
+            .into_iter()
+            .map(|node| Vote {
+                vote_type: self.vote_type.clone(),
+                height: self.height,
+                round: self.round,
+                proposal: self.proposal.clone(),
+                voter: node.address.clone(),
+            })
+            .collect())
+    }
+}
+
+/// A round change message. A node sends it when it gives up the current round
+/// and wants to move to the round.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RoundChange {
+    /// The height of the round change.
+    pub height: u64,
+    /// The round that the node wants to move to.
+    pub round: u64,
+    /// The address of the node.
+    pub voter: Address,
+}
+
+/// The reason why a proposal is rejected.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The proposer is not the one of the round.
+    WrongProposer,
+    /// The lock round of the proposal is after its round.
+    InvalidLockRound,
+    /// A lock vote is not a prevote on the proposal at its height and lock
+    /// round.
+    MismatchedLockVote,
+    /// A lock vote is from a node out of the authority list.
+    UnknownLockVoter,
+    /// Two lock votes are from the same node.
+    DuplicateLockVote,
+    /// The lock votes have no more than 2/3 of the vote weight.
+    InsufficientLockWeight,
+    /// The parent quorum certificate of a chained proposal is invalid.
+    InvalidQuorumCert,
+}
+
+/// An evidence of a misbehaving proposer, the proposal is rejected for the
+/// reason.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Evidence {
+    /// The reason of the rejection.
+    pub reason: RejectReason,
+    /// The rejected proposal.
+    pub proposal: Proposal,
+}
+
+/// A reply of `RequestProposal`, the application has got the content of the
+/// proposal.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProposalContent {
+    /// The height of the proposal.
+    pub height: u64,
+    /// The digest of the proposal.
+    pub digest: Target,
+}
+
 /// A proposal content for a height.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Feed {
@@ -171,10 +465,37 @@ pub struct Commit {
     pub round: u64,
     /// Consensus result.
     pub proposal: Target,
-    /// Precommit votes for generate proof.
+    /// Precommit votes for generate proof, or the prevotes of round 0 on the
+    /// fast path.
     pub lock_votes: Vec<Vote>,
     /// The node address.
     pub address: Address,
+    /// All the precommits of the round seen at the commit, including the ones
+    /// for nil or other proposals.
+    pub precommits: Vec<Vote>,
+    /// The authorities whose precommit of the round is not seen at the commit.
+    pub absent: Vec<Address>,
+    /// The vote weight of the precommits for the proposal.
+    pub precommit_weight: u64,
+    /// The total vote weight of the authorities.
+    pub total_weight: u64,
+    /// The number of rounds the height took, that is the round plus one.
+    pub rounds: u64,
+    /// The consensus time of the height in milliseconds.
+    pub elapsed: u64,
+    /// Whether the height is committed on the fast path. The proof is then the
+    /// prevotes of round 0 on the proposal over the fast path threshold, and
+    /// the precommits, the absent authorities and the precommit weight are of
+    /// the prevotes.
+    #[serde(default)]
+    pub fast_path: bool,
+}
+
+impl Commit {
+    /// A function to encode the lock votes into a bitmap of the authority list.
+    pub fn compact_lock_votes(&self, authority_list: &[Node]) -> Result<CompactVotes, BftError> {
+        CompactVotes::from_votes(authority_list, &self.lock_votes)
+    }
 }
 
 /// The rich status of a height.
@@ -255,3 +576,51 @@ pub struct VerifyResp {
     /// The verify proposal
     pub proposal: Target,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gen_vote(voter: u8) -> Vote {
+        Vote {
+            vote_type: VoteType::Prevote,
+            height: 1,
+            round: 2,
+            proposal: Target::new(vec![7]),
+            voter: Address::new(vec![voter]),
+        }
+    }
+
+    #[test]
+    fn test_compact_votes() {
+        let authority_list: Vec<Node> = (0..10).map(|i| Node::new(Address::new(vec![i]))).collect();
+        let votes: Vec<Vote> = [9, 0, 3].iter().map(|v| gen_vote(*v)).collect();
+        let proposal = Proposal {
+            height: 1,
+            round: 3,
+            content: Target::new(vec![7]),
+            lock_round: Some(2),
+            lock_votes: votes,
+            proposer: Address::new(vec![0]),
+            parent_qc: None,
+        };
+
+        let compact = proposal
+            .compact_lock_votes(&authority_list)
+            .unwrap()
+            .unwrap();
+        assert_eq!(compact.voters.iter().collect::<Vec<_>>(), vec![0, 3, 9]);
+        assert_eq!(compact.voters.count(), 3);
+        let decoded = compact.to_votes(&authority_list).unwrap();
+        assert_eq!(decoded, vec![gen_vote(0), gen_vote(3), gen_vote(9)]);
+
+        // the bitmap is of the authority list
+        assert!(compact.to_votes(&authority_list[..9]).is_err());
+        let mut votes = decoded;
+        votes.push(gen_vote(10));
+        assert!(CompactVotes::from_votes(&authority_list, &votes).is_err());
+        votes.pop();
+        votes.push(gen_vote(3));
+        assert!(CompactVotes::from_votes(&authority_list, &votes).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::types::{Address, Target, Vote, VoteType};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use lru_cache::LruCache;
 
@@ -40,7 +40,7 @@ impl VoteCollector {
                 {
                     // update prevote count hashmap
                     let counter = self.prevote_count.entry(round).or_insert(0);
-                    *counter += weight;
+                    *counter = counter.saturating_add(weight);
                     true
                 } else {
                     // if add prevote fail, do not update prevote hashmap
@@ -52,7 +52,7 @@ impl VoteCollector {
                 self.votes.insert(height, round_votes);
                 // update prevote count hashmap
                 let counter = self.prevote_count.entry(round).or_insert(0);
-                *counter += weight;
+                *counter = counter.saturating_add(weight);
                 true
             }
         } else if self.votes.contains_key(&height) {
@@ -80,6 +80,22 @@ impl VoteCollector {
             .and_then(|rc| rc.get_voteset(round, vote_type))
     }
 
+    /// A function to get the vote target of the sender at the height, the round
+    /// and the vote type.
+    pub(crate) fn get_vote(
+        &mut self,
+        height: u64,
+        round: u64,
+        vote_type: VoteType,
+        sender: &Address,
+    ) -> Option<Target> {
+        self.votes
+            .get_mut(&height)
+            .and_then(|rc| rc.round_votes.get_mut(&round))
+            .and_then(|sc| sc.step_votes.get(&vote_type))
+            .and_then(|vs| vs.votes_by_sender.get(sender).cloned())
+    }
+
     /// A function to clean prevote count HashMap at the begining of a height.
     pub(crate) fn clear_prevote_count(&mut self) {
         self.prevote_count.clear();
@@ -116,8 +132,9 @@ impl VoteSet {
             vote.to_owned()
         });
         if is_add {
-            self.count += weight;
-            *self.votes_by_proposal.entry(vote).or_insert(0) += weight;
+            self.count = self.count.saturating_add(weight);
+            let count = self.votes_by_proposal.entry(vote).or_insert(0);
+            *count = count.saturating_add(weight);
         }
         is_add
     }
@@ -228,3 +245,45 @@ impl StepCollector {
         self.step_votes.get(&vote_type).cloned()
     }
 }
+
+/// BFT round change collector.
+#[derive(Debug, Default)]
+pub(crate) struct RoundChangeCollector {
+    /// A HashMap that K is round, V is the senders of the round.
+    pub(crate) senders: HashMap<u64, HashSet<Address>>,
+    /// A HashMap that K is round, V is the weight sum of the round.
+    pub(crate) weight: HashMap<u64, u64>,
+}
+
+impl RoundChangeCollector {
+    /// A function to create a new round change collector.
+    pub(crate) fn new() -> Self {
+        RoundChangeCollector {
+            senders: HashMap::new(),
+            weight: HashMap::new(),
+        }
+    }
+
+    /// A function try to add a round change, return the weight sum of the
+    /// round if it is added.
+    pub(crate) fn add(&mut self, round: u64, sender: Address, weight: u64) -> Option<u64> {
+        if !self.senders.entry(round).or_default().insert(sender) {
+            return None;
+        }
+        let sum = self.weight.entry(round).or_insert(0);
+        *sum = sum.saturating_add(weight);
+        Some(*sum)
+    }
+
+    /// A function to remove the round changes which are not above the round.
+    pub(crate) fn remove_stale(&mut self, round: u64) {
+        self.senders.retain(|r, _| *r > round);
+        self.weight.retain(|r, _| *r > round);
+    }
+
+    /// A function to clean the round changes at the begining of a height.
+    pub(crate) fn clear(&mut self) {
+        self.senders.clear();
+        self.weight.clear();
+    }
+}
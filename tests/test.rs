@@ -79,7 +79,26 @@ impl BftTest {
                     match test_msg {
                         CoreOutput::Commit(c) => self.send_commit.send(c).unwrap(),
                         CoreOutput::GetProposalRequest(_h) => return,
-                        _ => self.send2test.send(test_msg.clone()).unwrap(),
+                        CoreOutput::PrepareProposal { .. } => return,
+                        CoreOutput::RoundChange(_rc) => return,
+                        CoreOutput::SendTo {
+                            msg: PeerMsg::RoundChange(_rc),
+                            ..
+                        } => return,
+                        CoreOutput::CommitUpdate(_c) => return,
+                        CoreOutput::Evidence(_e) => return,
+                        CoreOutput::RequestProposal { height, digest, .. } => self
+                            .bft
+                            .send_bft_msg(CoreInput::ProposalContent(ProposalContent {
+                                height,
+                                digest,
+                            }))
+                            .unwrap(),
+                        CoreOutput::Proposal(_)
+                        | CoreOutput::Vote(_)
+                        | CoreOutput::SendTo { .. } => {
+                            self.send2test.send(test_msg.clone()).unwrap()
+                        }
                     }
                 }
             }
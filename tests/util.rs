@@ -23,6 +23,7 @@ impl Support for TestSupport {
                     lock_round: p.lock_round,
                     lock_votes: into_bft_vote(p.lock_votes),
                     proposer: bft::Address::new(p.proposer),
+                    parent_qc: None,
                 }))
                 .unwrap(),
             FrameSend::Vote(v) => {
@@ -62,32 +63,14 @@ impl Support for TestSupport {
 
     fn recv(&self) -> FrameRecv {
         match self.recv.recv().unwrap() {
-            bft::CoreOutput::Proposal(p) => {
-                return FrameRecv::Proposal(Proposal {
-                    height: p.height,
-                    round: p.round,
-                    content: p.content.into_vec(),
-                    lock_round: p.lock_round,
-                    lock_votes: from_bft_vote(p.lock_votes),
-                    proposer: p.proposer.into_vec(),
-                })
-            }
-            bft::CoreOutput::Vote(v) => {
-                let vote_type = if v.vote_type == bft::VoteType::Prevote {
-                    VoteType::Prevote
-                } else {
-                    VoteType::Precommit
-                };
-
-                return FrameRecv::Vote(Vote {
-                    height: v.height,
-                    round: v.round,
-                    vote_type,
-                    proposal: v.proposal.into_vec(),
-                    voter: v.voter.into_vec(),
-                });
-            }
-            _ => panic!("Invalid message type!"),
+            bft::CoreOutput::Proposal(p) => from_bft_proposal(p),
+            bft::CoreOutput::Vote(v) => from_bft_vote_msg(v),
+            bft::CoreOutput::SendTo { msg, .. } => match msg {
+                bft::PeerMsg::Proposal(p) => from_bft_proposal(p),
+                bft::PeerMsg::Vote(v) => from_bft_vote_msg(v),
+                bft::PeerMsg::RoundChange(_) => unreachable!("round changes are dropped"),
+            },
+            _ => unreachable!("only proposals and votes are sent to the test"),
         }
     }
 
@@ -130,6 +113,33 @@ impl Support for TestSupport {
     }
 }
 
+fn from_bft_proposal(p: bft::Proposal) -> FrameRecv {
+    FrameRecv::Proposal(Proposal {
+        height: p.height,
+        round: p.round,
+        content: p.content.into_vec(),
+        lock_round: p.lock_round,
+        lock_votes: from_bft_vote(p.lock_votes),
+        proposer: p.proposer.into_vec(),
+    })
+}
+
+fn from_bft_vote_msg(v: bft::Vote) -> FrameRecv {
+    let vote_type = if v.vote_type == bft::VoteType::Prevote {
+        VoteType::Prevote
+    } else {
+        VoteType::Precommit
+    };
+
+    FrameRecv::Vote(Vote {
+        height: v.height,
+        round: v.round,
+        vote_type,
+        proposal: v.proposal.into_vec(),
+        voter: v.voter.into_vec(),
+    })
+}
+
 fn into_bft_vote(lock_votes: Vec<Vote>) -> Vec<bft::Vote> {
     let mut res = Vec::new();
     if lock_votes.len() != 0 {